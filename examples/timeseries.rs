@@ -11,8 +11,8 @@ fn main() {
 
     drawing_area.fill(&WHITE).unwrap();
 
-    let start_date = Utc.ymd(2019, 10, 1);
-    let end_date = Utc.ymd(2019, 10, 18);
+    let start_date = Utc.with_ymd_and_hms(2019, 10, 1, 0, 0, 0).unwrap();
+    let end_date = Utc.with_ymd_and_hms(2019, 10, 18, 0, 0, 0).unwrap();
 
     let mut chart = ChartBuilder::on(&drawing_area)
         .caption("Timeseries Test", ("sans-serif", 30))
@@ -31,7 +31,7 @@ fn main() {
         .draw_series(LineSeries::new(
             (0..).zip(DATA.iter()).map(|(idx, price)| {
                 let day = (idx / 5) * 7 + idx % 5 + 1;
-                let date = Utc.ymd(2019, 10, day);
+                let date = Utc.with_ymd_and_hms(2019, 10, day, 0, 0, 0).unwrap();
                 (date, *price)
             }),
             &BLUE,
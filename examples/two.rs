@@ -20,7 +20,7 @@ fn main() {
     chart
         // .draw_series(LineSeries::new((0..10).map(|x| (x, x * x)), &BLACK))
         .draw_series(
-            AreaSeries::new((0..=10).map(|x| (x, x * x)), 0, &BLUE.mix(0.3)).border_style(&BLUE),
+            AreaSeries::new((0..=10).map(|x| (x, x * x)), 0, BLUE.mix(0.3)).border_style(BLUE),
         )
         .unwrap();
 
@@ -0,0 +1,113 @@
+use jiff::{Timestamp, tz::TimeZone};
+use miette::Result;
+
+use crate::{
+    heatmap::Heatmap,
+    promql::{self, InstantValue},
+    series::{Frame, Renderer},
+    terminal,
+    units::Unit,
+};
+
+pub struct BackendPlain {
+    unit: Unit,
+}
+
+impl BackendPlain {
+    pub fn new(unit: Unit) -> Self {
+        Self { unit }
+    }
+}
+
+impl Renderer for BackendPlain {
+    /// A sample per terminal column, as if the table were a chart.
+    fn points(&self) -> u32 {
+        let (columns, _) = terminal::size();
+        columns as u32
+    }
+
+    fn render(&self, _title: &str, frame: &Frame) -> Result<String> {
+        let mut rows: Vec<(String, f64, f64, f64)> = frame
+            .series
+            .iter()
+            .map(|s| {
+                let stats = s.stats();
+                (s.name.clone(), stats.min, stats.max, stats.last)
+            })
+            .collect();
+
+        if rows.is_empty() {
+            return Ok("No valid data points".to_string());
+        }
+
+        rows.sort_by(|a, b| b.3.total_cmp(&a.3));
+
+        let label_width = rows
+            .iter()
+            .map(|r| r.0.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let mut out = format!(
+            "{:<label_width$}  {:>12}  {:>12}  {:>12}",
+            "SERIES", "MIN", "MAX", "LAST"
+        );
+        for (label, min, max, last) in rows {
+            out.push_str(&format!(
+                "\n{:<label_width$}  {:>12}  {:>12}  {:>12}",
+                label,
+                self.unit.format(min),
+                self.unit.format(max),
+                self.unit.format(last)
+            ));
+        }
+        Ok(out)
+    }
+
+    fn render_instant(&self, _title: &str, mut data: Vec<InstantValue>) -> Result<String> {
+        promql::sort_instant(&mut data);
+
+        let labels: Vec<String> = data.iter().map(|v| v.label.clone()).collect();
+        let label_width = labels
+            .iter()
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(6);
+        let mut out = format!("{:<label_width$}  {:>12}", "SERIES", "VALUE");
+        for (label, v) in labels.iter().zip(data.iter()) {
+            out.push_str(&format!(
+                "\n{:<label_width$}  {:>12}",
+                label,
+                self.unit.format(v.value)
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Lists the observations in each histogram bucket, a row per timestamp and a
+    /// column per bucket named after its upper bound.
+    fn render_heatmap(&self, _title: &str, heatmap: &Heatmap) -> Result<String> {
+        let mut out = format!("{:<19}", "TIME");
+        for bucket in 0..heatmap.bounds.len() {
+            out.push_str(&format!(
+                "  {:>12}",
+                format!("le {}", heatmap.bucket_label(bucket, self.unit))
+            ));
+        }
+        for (ts, counts) in heatmap.timestamps.iter().zip(&heatmap.counts) {
+            let time = match Timestamp::from_second(*ts as i64) {
+                Ok(t) => t
+                    .to_zoned(TimeZone::system())
+                    .strftime("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+                Err(_) => ts.to_string(),
+            };
+            out.push_str(&format!("\n{:<19}", time));
+            for count in counts {
+                out.push_str(&format!("  {:>12.2}", count));
+            }
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,420 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, TimeZone};
+use miette::{IntoDiagnostic, Result, miette};
+use plotters::{chart::SeriesAnno, coord::Shift, prelude::*};
+
+use crate::{
+    heatmap::{self, Heatmap},
+    promql::InstantValue,
+    series::{Frame, Renderer, Series},
+    style::{self, ChartStyle},
+    units::Unit,
+};
+
+const COLORS: &[(u8, u8, u8)] = &[
+    (0, 160, 0),   // Green
+    (220, 0, 0),   // Red
+    (200, 160, 0), // Yellow
+    (200, 0, 200), // Magenta
+    (0, 160, 200), // Cyan
+    (240, 130, 0), // Orange
+    (128, 0, 128), // Purple
+    (0, 0, 220),   // Blue
+];
+
+const MARGIN: u32 = 10;
+const Y_LABEL_AREA: u32 = 80;
+
+/// Width of a sample's slot in bar charts, shared by the bars of all series.
+const BAR_PIXELS: u32 = 12;
+
+/// Width of a heatmap cell.
+const HEATMAP_PIXELS: u32 = 4;
+
+pub struct BackendPlotters {
+    output: PathBuf,
+    format: OutputFormat,
+    width: u32,
+    height: u32,
+    unit: Unit,
+    style: ChartStyle,
+}
+
+/// Image format written, from the extension of the output file.
+#[derive(Clone, Copy, Debug)]
+pub enum OutputFormat {
+    Png,
+    Svg,
+}
+
+impl OutputFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("png") => Ok(Self::Png),
+            Some("svg") => Ok(Self::Svg),
+            _ => Err(miette!(
+                help = "use a file name ending in .png or .svg",
+                "unsupported output format: {}",
+                path.display()
+            )),
+        }
+    }
+}
+
+impl BackendPlotters {
+    pub fn new(
+        output: PathBuf,
+        format: OutputFormat,
+        width: u32,
+        height: u32,
+        unit: Unit,
+        style: ChartStyle,
+    ) -> Self {
+        Self {
+            output,
+            format,
+            width,
+            height,
+            unit,
+            style,
+        }
+    }
+}
+
+impl Renderer for BackendPlotters {
+    /// Horizontal resolution of the plotting area in pixels, without the y-axis labels.
+    /// Bars take a few pixels each, so they get fewer samples.
+    fn points(&self) -> u32 {
+        let width = self.width.saturating_sub(Y_LABEL_AREA + 2 * MARGIN);
+        match self.style {
+            ChartStyle::Bars => width / BAR_PIXELS,
+            _ => width,
+        }
+    }
+
+    /// Horizontal resolution of heatmaps, in cells.
+    fn heatmap_points(&self) -> u32 {
+        self.width.saturating_sub(Y_LABEL_AREA + 2 * MARGIN) / HEATMAP_PIXELS
+    }
+
+    fn render(&self, title: &str, frame: &Frame) -> Result<String> {
+        let series = parse_series(frame, self.style);
+        if series.is_empty() {
+            return Err(miette!("no valid data points"));
+        }
+        let y_bounds = frame.y_bounds(self.style);
+
+        let size = (self.width, self.height);
+        match self.format {
+            OutputFormat::Png => {
+                let root = BitMapBackend::new(&self.output, size).into_drawing_area();
+                draw(&root, title, &series, y_bounds, self.unit, self.style)?;
+                root.present().into_diagnostic()?;
+            }
+            OutputFormat::Svg => {
+                let root = SVGBackend::new(&self.output, size).into_drawing_area();
+                draw(&root, title, &series, y_bounds, self.unit, self.style)?;
+                root.present().into_diagnostic()?;
+            }
+        }
+
+        Ok(format!(
+            "Wrote {} series to {}",
+            series.len(),
+            self.output.display()
+        ))
+    }
+
+    fn render_instant(&self, _title: &str, _values: Vec<InstantValue>) -> Result<String> {
+        Err(miette!(
+            help = "use the plain, textplots or ratatui backend",
+            "instant queries are not supported by the plotters backend"
+        ))
+    }
+
+    fn render_heatmap(&self, title: &str, heatmap: &Heatmap) -> Result<String> {
+        let size = (self.width, self.height);
+        match self.format {
+            OutputFormat::Png => {
+                let root = BitMapBackend::new(&self.output, size).into_drawing_area();
+                draw_heatmap(&root, title, heatmap, self.unit)?;
+                root.present().into_diagnostic()?;
+            }
+            OutputFormat::Svg => {
+                let root = SVGBackend::new(&self.output, size).into_drawing_area();
+                draw_heatmap(&root, title, heatmap, self.unit)?;
+                root.present().into_diagnostic()?;
+            }
+        }
+
+        Ok(format!(
+            "Wrote {} buckets to {}",
+            heatmap.bounds.len(),
+            self.output.display()
+        ))
+    }
+}
+
+struct SeriesData {
+    label: String,
+    /// Runs of samples between gaps, each drawn as its own line
+    segments: Vec<Vec<(DateTime<Local>, f64)>>,
+    /// NaN and infinite samples, marked on the edges of the chart
+    invalid: Vec<(DateTime<Local>, f64)>,
+}
+
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    series: &[SeriesData],
+    (y_min, y_max): (f64, f64),
+    unit: Unit,
+    style: ChartStyle,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE).into_diagnostic()?;
+
+    let times = series
+        .iter()
+        .flat_map(|s| s.segments.iter().flatten().chain(&s.invalid))
+        .map(|&(x, _)| x);
+    let (Some(x_min), Some(mut x_max)) = (times.clone().min(), times.max()) else {
+        return Err(miette!("no valid data points"));
+    };
+    // A single sample would give an empty x range
+    if x_min == x_max {
+        x_max = x_min + chrono::Duration::seconds(1);
+    }
+
+    let time_format = if x_max - x_min > chrono::Duration::days(1) {
+        "%m-%d %H:%M"
+    } else {
+        "%H:%M"
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 20))
+        .margin(MARGIN)
+        .set_label_area_size(LabelAreaPosition::Left, Y_LABEL_AREA)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)
+        .into_diagnostic()?;
+
+    chart
+        .configure_mesh()
+        .x_desc("Time")
+        .y_desc("Value")
+        .x_label_formatter(&|t| t.format(time_format).to_string())
+        .y_label_formatter(&|v| unit.format(*v))
+        .draw()
+        .into_diagnostic()?;
+
+    // Bars of different series share a sample's slot side by side
+    let slot = series
+        .iter()
+        .flat_map(|s| &s.segments)
+        .flat_map(|points| points.windows(2).map(|pair| pair[1].0 - pair[0].0))
+        .min()
+        .unwrap_or(chrono::Duration::seconds(1));
+    let bar_width = slot * 4 / 5 / series.len() as i32;
+
+    // Stacks are drawn from the top so the lower areas stay visible
+    let order: Vec<usize> = match style {
+        ChartStyle::Stacked => (0..series.len()).rev().collect(),
+        _ => (0..series.len()).collect(),
+    };
+
+    for i in order {
+        let s = &series[i];
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        let color = RGBColor(r, g, b);
+        // One legend entry per series, however many pieces it is drawn in
+        let mut labelled = false;
+        let mut label = |annotation: &mut SeriesAnno<DB>| {
+            if !labelled {
+                annotation.label(s.label.clone()).legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+                labelled = true;
+            }
+        };
+        for segment in &s.segments {
+            let points = segment.iter().copied();
+            let annotation = match style {
+                // A lone sample between gaps makes no line
+                ChartStyle::Line | ChartStyle::Steps | ChartStyle::Area if segment.len() == 1 => {
+                    chart.draw_series(points.map(|p| Circle::new(p, 2, color.filled())))
+                }
+                ChartStyle::Line => {
+                    chart.draw_series(LineSeries::new(points, color.stroke_width(2)))
+                }
+                ChartStyle::Area => chart.draw_series(
+                    AreaSeries::new(points, 0.0, color.mix(0.2))
+                        .border_style(color.stroke_width(2)),
+                ),
+                ChartStyle::Stacked => chart.draw_series(
+                    AreaSeries::new(points, 0.0, color.mix(0.8))
+                        .border_style(color.stroke_width(1)),
+                ),
+                ChartStyle::Bars => {
+                    let offset = bar_width * i as i32 - slot * 2 / 5;
+                    chart.draw_series(points.map(|(t, y)| {
+                        let start = t + offset;
+                        Rectangle::new([(start, 0.0), (start + bar_width, y)], color.filled())
+                    }))
+                }
+                ChartStyle::Points => {
+                    chart.draw_series(points.map(|p| Circle::new(p, 3, color.filled())))
+                }
+                ChartStyle::Steps => chart.draw_series(LineSeries::new(
+                    style::steps(segment),
+                    color.stroke_width(2),
+                )),
+            };
+            label(annotation.into_diagnostic()?);
+        }
+        if !s.invalid.is_empty() {
+            let annotation = chart.draw_series(s.invalid.iter().map(|&(t, y)| {
+                let y = crate::series::edge(y, y_min, y_max);
+                Cross::new((t, y), 5, color.stroke_width(2))
+            }));
+            label(annotation.into_diagnostic()?);
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .into_diagnostic()?;
+
+    Ok(())
+}
+
+/// Draws a rectangle per sample and bucket, buckets stacked in order of their bounds.
+fn draw_heatmap<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    heatmap: &Heatmap,
+    unit: Unit,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE).into_diagnostic()?;
+
+    let times: Vec<DateTime<Local>> = heatmap
+        .timestamps
+        .iter()
+        .filter_map(|&ts| Local.timestamp_opt(ts as i64, 0).single())
+        .collect();
+    let (Some(&x_min), Some(&last)) = (times.first(), times.last()) else {
+        return Err(miette!("no valid data points"));
+    };
+    // Each sample covers the time up to the next one
+    let slot = times
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .min()
+        .unwrap_or(chrono::Duration::seconds(1));
+    let x_max = last + slot;
+    let buckets = heatmap.bounds.len() as i32;
+
+    let time_format = if x_max - x_min > chrono::Duration::days(1) {
+        "%m-%d %H:%M"
+    } else {
+        "%H:%M"
+    };
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 20))
+        .margin(MARGIN)
+        .set_label_area_size(LabelAreaPosition::Left, Y_LABEL_AREA)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(x_min..x_max, 0..buckets)
+        .into_diagnostic()?;
+
+    // A bucket's bound labels its upper edge
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("Time")
+        .y_desc("Bucket")
+        .y_labels(buckets as usize + 1)
+        .x_label_formatter(&|t| t.format(time_format).to_string())
+        .y_label_formatter(&|&edge| match edge {
+            1.. => heatmap.bucket_label(edge as usize - 1, unit),
+            _ => String::new(),
+        })
+        .draw()
+        .into_diagnostic()?;
+
+    let max = heatmap.counts.iter().flatten().copied().fold(0.0, f64::max);
+    for (&time, counts) in times.iter().zip(&heatmap.counts) {
+        chart
+            .draw_series(counts.iter().enumerate().filter_map(|(bucket, &count)| {
+                let (r, g, b) = heatmap::color(count, max)?;
+                let bucket = bucket as i32;
+                Some(Rectangle::new(
+                    [(time, bucket), (time + slot, bucket + 1)],
+                    RGBColor(r, g, b).filled(),
+                ))
+            }))
+            .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+/// Converts the samples to local times, summing the series first for stacked charts,
+/// whose totals break where a series below has no value.
+fn parse_series(frame: &Frame, style: ChartStyle) -> Vec<SeriesData> {
+    let segments: Vec<Vec<Vec<(f64, f64)>>> = match style {
+        ChartStyle::Stacked => {
+            let series: Vec<&Series> = frame.series.iter().collect();
+            style::stack(&series, frame.null_as)
+                .iter()
+                .map(Series::segments)
+                .collect()
+        }
+        _ => frame.series.iter().map(|s| s.segments()).collect(),
+    };
+    let local = |samples: &[(f64, f64)]| -> Vec<(DateTime<Local>, f64)> {
+        samples
+            .iter()
+            .filter_map(|&(ts, value)| {
+                let secs = ts.trunc() as i64;
+                let nanos = (ts.fract() * 1e9) as u32;
+                Local
+                    .timestamp_opt(secs, nanos)
+                    .single()
+                    .map(|t| (t, value))
+            })
+            .collect()
+    };
+
+    frame
+        .series
+        .iter()
+        .zip(segments)
+        .map(|(s, segments)| SeriesData {
+            label: s.name.clone(),
+            segments: segments
+                .iter()
+                .map(|segment| local(segment))
+                .filter(|segment| !segment.is_empty())
+                .collect(),
+            invalid: local(&s.invalid()),
+        })
+        .filter(|s| !s.segments.is_empty() || !s.invalid.is_empty())
+        .collect()
+}
@@ -1,19 +1,47 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::BTreeSet,
+    io,
+    time::{Duration, Instant},
+};
 
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        MouseButton, MouseEventKind,
+    },
+    execute,
+};
 use jiff::{Timestamp, tz::TimeZone};
 use miette::{IntoDiagnostic, Result};
-use prometheus_http_query::response::RangeVector;
+use prometheus_http_query::Client;
 use ratatui::{
-    DefaultTerminal,
-    layout::{Constraint, Layout},
-    style::{Color, Style},
+    DefaultTerminal, Frame, Terminal,
+    backend::TestBackend,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style},
     symbols::Marker,
     text::{Line, Span},
-    widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph, Wrap},
+    widgets::{
+        Axis, Bar, BarChart, Block, Borders, Chart, Clear, Dataset, GraphType, LineGauge, List,
+        ListItem, ListState, Paragraph, Wrap,
+    },
+};
+use regex::Regex;
+
+use crate::{
+    heatmap::{self, Heatmap},
+    histogram,
+    promql::{self, InstantValue, Query, RangeSeries},
+    series::{self, Gaps, Renderer, Series},
+    style::{self as chart_style, ChartStyle},
+    terminal,
+    timerange::{Step, TimeRange},
+    units::Unit,
+    variables::{self, Variable},
 };
 
-use crate::promql;
+/// Share of the window the arrow keys pan by.
+const PAN_FRACTION: f64 = 0.25;
 
 const COLORS: &[(u8, u8, u8)] = &[
     (0, 252, 0),   // Green
@@ -27,45 +55,393 @@ const COLORS: &[(u8, u8, u8)] = &[
 ];
 
 pub struct BackendRatatui {
-    addr: String,
-    expr: String,
-    step: f64,
-    duration: u16,
+    client: Client,
+    /// Expressions before variable substitution
+    queries: Vec<Query>,
+    variables: Vec<Variable>,
+    range: TimeRange,
     refresh: u64,
+    options: ChartOptions,
+    /// Series read from `--input`, shown instead of querying
+    input: Option<Vec<(String, RangeSeries)>>,
+}
+
+/// What a chart queries and how it draws the results, for the backend and for each
+/// panel of a dashboard.
+#[derive(Clone)]
+pub struct ChartOptions {
+    pub mode: Mode,
+    pub step: Step,
+    /// Quantiles drawn for native histograms
+    pub quantiles: Vec<f64>,
+    pub unit: Unit,
+    pub style: ChartStyle,
+    pub gaps: Gaps,
+}
+
+impl ChartOptions {
+    /// The step in seconds for `range` drawn `points` braille dots wide.
+    pub fn step_seconds(&self, range: &TimeRange, points: u32) -> f64 {
+        match self.mode {
+            // A heatmap cell is a whole column wide rather than a braille dot
+            Mode::Heatmap => self.step.seconds(range, points / 2),
+            Mode::Range | Mode::Instant => self.step.seconds(range, points),
+        }
+    }
+}
+
+/// What a chart queries and how it shows the result.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Range queries drawn as time series
+    Range,
+    /// Instant queries drawn as gauges or bars
+    Instant,
+    /// Range queries over histogram buckets drawn as a heatmap
+    Heatmap,
+}
+
+pub(crate) enum PanelData {
+    Range(Vec<Series>),
+    Instant(Vec<InstantValue>),
+    Heatmap(Heatmap),
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum LegendSort {
+    #[default]
+    None,
+    Min,
+    Max,
+    Avg,
+    Last,
+}
+
+impl LegendSort {
+    fn next(self) -> Self {
+        match self {
+            LegendSort::None => LegendSort::Min,
+            LegendSort::Min => LegendSort::Max,
+            LegendSort::Max => LegendSort::Avg,
+            LegendSort::Avg => LegendSort::Last,
+            LegendSort::Last => LegendSort::None,
+        }
+    }
+
+    fn key(self, stats: &series::Stats) -> f64 {
+        match self {
+            LegendSort::None => 0.0,
+            LegendSort::Min => stats.min,
+            LegendSort::Max => stats.max,
+            LegendSort::Avg => stats.avg,
+            LegendSort::Last => stats.last,
+        }
+    }
+}
+
+/// Which series the chart shows and how the legend lists them. Series are tracked
+/// by label so the choices survive refreshes.
+#[derive(Default)]
+pub(crate) struct Legend {
+    state: ListState,
+    hidden: BTreeSet<String>,
+    filter: Option<Regex>,
+    /// Filter being typed after `/`
+    input: Option<String>,
+    error: Option<String>,
+    sort: LegendSort,
+}
+
+impl Legend {
+    /// Indexes of the series the legend lists, in display order.
+    fn entries(&self, series: &[Series]) -> Vec<usize> {
+        let mut entries: Vec<usize> = (0..series.len())
+            .filter(|&i| self.matches(&series[i]))
+            .collect();
+        if self.sort != LegendSort::None {
+            // Largest first, like the plain backend's table
+            entries.sort_by(|&a, &b| {
+                let a = self.sort.key(&series[a].stats());
+                let b = self.sort.key(&series[b].stats());
+                b.total_cmp(&a)
+            });
+        }
+        entries
+    }
+
+    fn is_visible(&self, series: &Series) -> bool {
+        !self.hidden.contains(&series.name) && self.matches(series)
+    }
+
+    /// Whether the filter matches the series' name or one of its labels as
+    /// `name=value`, which legend formats may leave out of the name.
+    fn matches(&self, series: &Series) -> bool {
+        self.filter.as_ref().is_none_or(|re| {
+            re.is_match(&series.name)
+                || series
+                    .labels
+                    .iter()
+                    .any(|(key, value)| re.is_match(&format!("{}={}", key, value)))
+        })
+    }
+
+    fn selected<'a>(&self, series: &'a [Series]) -> Option<&'a Series> {
+        let index = *self.entries(series).get(self.state.selected()?)?;
+        Some(&series[index])
+    }
+
+    /// Handles the legend keys, returning whether `code` was one of them.
+    fn handle(&mut self, code: KeyCode, series: &[Series]) -> bool {
+        let count = self.entries(series).len();
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j')
+                if self.state.selected().is_none_or(|i| i + 1 < count) =>
+            {
+                self.state.select_next()
+            }
+            KeyCode::Char(' ') => {
+                if let Some(s) = self.selected(series)
+                    && !self.hidden.remove(&s.name)
+                {
+                    self.hidden.insert(s.name.clone());
+                }
+            }
+            KeyCode::Enter => {
+                let Some(selected) = self.selected(series) else {
+                    return true;
+                };
+                let others: BTreeSet<String> = series
+                    .iter()
+                    .filter(|s| s.name != selected.name)
+                    .map(|s| s.name.clone())
+                    .collect();
+                // Isolating the isolated series again brings the others back
+                self.hidden = if self.hidden == others {
+                    BTreeSet::new()
+                } else {
+                    others
+                };
+            }
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Edits the filter being typed: enter applies it, esc cancels.
+    fn edit(&mut self, code: KeyCode) {
+        let Some(input) = &mut self.input else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                let input = self.input.take().unwrap_or_default();
+                self.error = None;
+                self.filter = None;
+                if !input.is_empty() {
+                    match Regex::new(&input) {
+                        Ok(re) => self.filter = Some(re),
+                        Err(err) => self.error = Some(err.to_string()),
+                    }
+                }
+                self.state.select(Some(0));
+            }
+            _ => {}
+        }
+    }
+
+    fn title(&self) -> String {
+        if let Some(input) = &self.input {
+            return format!(" Filter: {}█ ", input);
+        }
+        let mut title = " Legend ".to_string();
+        if let Some(re) = &self.filter {
+            title += &format!("| /{}/ ", re);
+        }
+        if let Some(err) = &self.error {
+            title += &format!(
+                "| invalid filter: {} ",
+                err.lines().last().unwrap_or_default()
+            );
+        }
+        let sort = match self.sort {
+            LegendSort::None => None,
+            LegendSort::Min => Some("min"),
+            LegendSort::Max => Some("max"),
+            LegendSort::Avg => Some("avg"),
+            LegendSort::Last => Some("last"),
+        };
+        if let Some(sort) = sort {
+            title += &format!("| sort: {} ", sort);
+        }
+        title
+    }
+}
+
+/// What the user changed interactively since starting.
+struct View {
+    variables: Vec<Variable>,
+    range: TimeRange,
+    /// Whether periodic refreshes are suspended
+    paused: bool,
+    /// The window before pausing, if it followed the clock, to go back to on resuming
+    resume: Option<TimeRange>,
+    /// Timestamp of the sample the crosshair is on
+    cursor: Option<f64>,
+    legend: Legend,
+    /// Why the last fetch failed, while the chart still shows the data before it
+    error: Option<String>,
+}
+
+impl View {
+    /// Applies the zoom, pan and pause keys, returning whether the window changed.
+    fn navigate(&mut self, code: KeyCode, initial: &TimeRange) -> bool {
+        match code {
+            KeyCode::Char('+' | '=') => self.range = self.range.zoom(0.5),
+            KeyCode::Char('-') => self.range = self.range.zoom(2.0),
+            KeyCode::Left => self.range = self.range.pan(-PAN_FRACTION),
+            KeyCode::Right => self.range = self.range.pan(PAN_FRACTION),
+            KeyCode::Char('0') => {
+                self.range = initial.clone();
+                self.paused = false;
+                self.resume = None;
+                return true;
+            }
+            KeyCode::Char('p') => {
+                self.toggle_pause();
+                return true;
+            }
+            _ => return false,
+        }
+        // A window that no longer ends now has nothing new to show
+        if !self.range.is_live() {
+            self.paused = true;
+            self.resume = None;
+        }
+        true
+    }
+
+    /// Moves the crosshair `by` samples, staying on the chart.
+    fn move_cursor(&mut self, series: &[Series], by: isize) {
+        let timestamps = timestamps(series);
+        let Some(cursor) = self.cursor else {
+            return;
+        };
+        let current = timestamps
+            .iter()
+            .position(|&ts| ts >= cursor)
+            .unwrap_or(timestamps.len().saturating_sub(1));
+        let index = current
+            .saturating_add_signed(by)
+            .min(timestamps.len().saturating_sub(1));
+        self.cursor = timestamps.get(index).copied();
+    }
+
+    /// Pausing freezes the window where it is. Resuming makes it follow the clock
+    /// again as it did before pausing, or ending now if it was zoomed or panned since.
+    fn toggle_pause(&mut self) {
+        let (start, end) = self.range.bounds();
+        if self.paused {
+            self.range = self
+                .resume
+                .take()
+                .unwrap_or_else(|| TimeRange::live(end - start));
+            self.paused = false;
+        } else {
+            self.resume = self.range.is_relative().then(|| self.range.clone());
+            self.range = TimeRange::fixed(start, end);
+            self.paused = true;
+        }
+    }
 }
 
-struct SeriesData {
-    label: String,
-    points: Vec<(f64, f64)>,
+/// Popup choosing a variable, then one of its values.
+struct Picker {
+    /// The variable whose values are listed, `None` while choosing the variable
+    variable: Option<usize>,
+    items: Vec<String>,
+    /// Typed text narrowing down `items`
+    filter: String,
+    state: ListState,
+    error: Option<String>,
 }
 
-impl SeriesData {
-    fn stats(&self) -> (f64, f64, f64) {
-        let mut min = f64::INFINITY;
-        let mut max = f64::NEG_INFINITY;
-        for &(_, y) in &self.points {
-            min = min.min(y);
-            max = max.max(y);
+impl Picker {
+    fn variables(variables: &[Variable]) -> Self {
+        let items = variables.iter().map(|v| v.name.clone()).collect();
+        Self::new(None, items, None)
+    }
+
+    fn new(variable: Option<usize>, items: Vec<String>, selected: Option<&str>) -> Self {
+        let index = selected
+            .and_then(|selected| items.iter().position(|item| item == selected))
+            .unwrap_or(0);
+        Self {
+            variable,
+            items,
+            filter: String::new(),
+            state: ListState::default().with_selected(Some(index)),
+            error: None,
         }
-        let last = self.points.last().map(|&(_, y)| y).unwrap_or(0.0);
-        (min, max, last)
+    }
+
+    fn visible(&self) -> Vec<&String> {
+        self.items
+            .iter()
+            .filter(|item| item.contains(&self.filter))
+            .collect()
+    }
+
+    fn selected(&self) -> Option<String> {
+        let index = self.state.selected()?;
+        self.visible().get(index).map(|item| item.to_string())
+    }
+
+    fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.state.select(Some(0));
     }
 }
 
 impl BackendRatatui {
-    pub fn new(addr: String, expr: String, step: f64, duration: u16, refresh: u64) -> Self {
+    pub fn new(
+        client: Client,
+        queries: Vec<Query>,
+        variables: Vec<Variable>,
+        range: TimeRange,
+        refresh: u64,
+        options: ChartOptions,
+        input: Option<Vec<(String, RangeSeries)>>,
+    ) -> Self {
         Self {
-            addr,
-            expr,
-            step,
-            duration,
+            client,
+            queries,
+            variables,
+            range,
             refresh,
+            options,
+            input,
         }
     }
 
     pub async fn run(&self) -> Result<()> {
         let mut terminal = ratatui::init();
-        let result = self.event_loop(&mut terminal).await;
+        // The terminal is restored whatever fails, or the shell is left in raw mode
+        let result = match execute!(io::stdout(), EnableMouseCapture).into_diagnostic() {
+            Ok(()) => {
+                let result = self.event_loop(&mut terminal).await;
+                let disabled = execute!(io::stdout(), DisableMouseCapture).into_diagnostic();
+                result.and(disabled)
+            }
+            Err(err) => Err(err),
+        };
         ratatui::restore();
         result
     }
@@ -73,201 +449,882 @@ impl BackendRatatui {
     async fn event_loop(&self, terminal: &mut DefaultTerminal) -> Result<()> {
         let refresh_interval = Duration::from_secs(self.refresh);
         let mut last_fetch = Instant::now();
-        let mut series = self.fetch_series().await?;
+        let mut view = View {
+            variables: self.variables.clone(),
+            range: self.range.clone(),
+            paused: false,
+            resume: None,
+            cursor: None,
+            legend: Legend::default(),
+            error: None,
+        };
+        let mut picker: Option<Picker> = None;
+        let mut changed = false;
+        let mut data = self.fetch_data(&view).await?;
 
         loop {
-            self.draw(terminal, &series)?;
+            let plot = self.draw(terminal, &data, &mut view, picker.as_mut())?;
+            let series = match &data {
+                PanelData::Range(series) => series.as_slice(),
+                PanelData::Instant(_) | PanelData::Heatmap(_) => &[],
+            };
 
             if event::poll(Duration::from_millis(250)).into_diagnostic()? {
-                if let Event::Key(key) = event::read().into_diagnostic()? {
-                    if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
-                        return Ok(());
+                match event::read().into_diagnostic()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        if let Some(open) = picker.take() {
+                            picker = self.pick(open, key, &mut view).await;
+                            // A value may have changed, so refetch right away
+                            changed = picker.is_none();
+                            continue;
+                        }
+                        if view.legend.input.is_some() {
+                            view.legend.edit(key.code);
+                            continue;
+                        }
+                        match key.code {
+                            KeyCode::Char('q') => return Ok(()),
+                            KeyCode::Char('v') if view.variables.len() == 1 => {
+                                picker = Some(self.values_picker(0, &view).await);
+                            }
+                            KeyCode::Char('v') if !view.variables.is_empty() => {
+                                picker = Some(Picker::variables(&view.variables));
+                            }
+                            KeyCode::Char('c') => {
+                                view.cursor = match view.cursor {
+                                    Some(_) => None,
+                                    None => timestamps(series).last().copied(),
+                                };
+                            }
+                            KeyCode::Esc => view.cursor = None,
+                            KeyCode::Left if view.cursor.is_some() => {
+                                view.move_cursor(series, -1);
+                            }
+                            KeyCode::Right if view.cursor.is_some() => {
+                                view.move_cursor(series, 1);
+                            }
+                            code if view.legend.handle(code, series) => {}
+                            code => changed = view.navigate(code, &self.range),
+                        }
+                    }
+                    Event::Mouse(mouse) => {
+                        if let (MouseEventKind::Down(MouseButton::Left), Some(plot)) =
+                            (mouse.kind, plot)
+                            && plot.area.contains((mouse.column, mouse.row).into())
+                        {
+                            view.cursor = nearest(series, plot.timestamp_at(mouse.column));
+                        }
                     }
+                    _ => {}
                 }
             }
 
-            if last_fetch.elapsed() >= refresh_interval {
-                if let Ok(new_series) = self.fetch_series().await {
-                    series = new_series;
+            let due = !view.paused && last_fetch.elapsed() >= refresh_interval;
+            if picker.is_none() && (changed || due) {
+                match self.fetch_data(&view).await {
+                    Ok(new_data) => {
+                        data = new_data;
+                        view.error = None;
+                        // Samples move with the window, keep the cursor on one
+                        if let (Some(cursor), PanelData::Range(series)) = (view.cursor, &data) {
+                            view.cursor = nearest(series, cursor);
+                        }
+                    }
+                    Err(err) => {
+                        let causes: Vec<String> = err.chain().map(|e| e.to_string()).collect();
+                        view.error = Some(causes.join(": "));
+                    }
                 }
                 last_fetch = Instant::now();
+                changed = false;
             }
         }
     }
 
-    async fn fetch_series(&self) -> Result<Vec<SeriesData>> {
-        let data = promql::get_data(&self.addr, &self.expr, self.step, self.duration).await?;
-        Ok(parse_series(&data))
+    /// Handles a key while the picker is open, returning it unless it was closed.
+    async fn pick(&self, mut picker: Picker, key: KeyEvent, view: &mut View) -> Option<Picker> {
+        let count = picker.visible().len();
+        match key.code {
+            KeyCode::Esc => return None,
+            KeyCode::Up => picker.state.select_previous(),
+            KeyCode::Down if picker.state.selected() < Some(count.saturating_sub(1)) => {
+                picker.state.select_next()
+            }
+            KeyCode::Backspace => {
+                let mut filter = picker.filter.clone();
+                filter.pop();
+                picker.set_filter(filter);
+            }
+            KeyCode::Char(c) => picker.set_filter(format!("{}{}", picker.filter, c)),
+            KeyCode::Enter => {
+                let selected = picker.selected()?;
+                return match picker.variable {
+                    Some(index) => {
+                        view.variables[index].value = Some(selected);
+                        None
+                    }
+                    None => {
+                        let index = view.variables.iter().position(|v| v.name == selected)?;
+                        Some(self.values_picker(index, view).await)
+                    }
+                };
+            }
+            _ => {}
+        }
+        Some(picker)
     }
 
-    fn draw(&self, terminal: &mut DefaultTerminal, series: &[SeriesData]) -> Result<()> {
+    async fn values_picker(&self, index: usize, view: &View) -> Picker {
+        let var = &view.variables[index];
+        match var.source.fetch(&self.client, &view.range).await {
+            Ok(values) => Picker::new(Some(index), values, var.value.as_deref()),
+            Err(err) => {
+                let mut picker = Picker::new(Some(index), Vec::new(), None);
+                picker.error = Some(format!("{:?}", err));
+                picker
+            }
+        }
+    }
+
+    async fn fetch_data(&self, view: &View) -> Result<PanelData> {
+        // Zooming and panning over a file cuts its samples to the window
+        if let Some(input) = &self.input {
+            let (start, end) = view.range.bounds();
+            let data = input
+                .iter()
+                .map(|(label, v)| (label.clone(), v.between(start as f64, end as f64)))
+                .collect();
+            return range_panel(data, &self.options);
+        }
+        let (columns, _) = terminal::size();
+        fetch_panel(
+            &self.client,
+            &variables::apply(&self.queries, &view.variables),
+            &view.range,
+            &self.options,
+            plot_width(columns),
+        )
+        .await
+    }
+
+    fn draw(
+        &self,
+        terminal: &mut DefaultTerminal,
+        data: &PanelData,
+        view: &mut View,
+        picker: Option<&mut Picker>,
+    ) -> Result<Option<Plot>> {
+        let mut title = format!(" {} ", promql::title(&self.queries));
+        for var in &view.variables {
+            title += &format!(
+                "| {}={} ",
+                var.name,
+                var.value.as_deref().unwrap_or_default()
+            );
+        }
+        title += &format!("| {} ", format_range(&view.range));
+
+        let status = if let Some(err) = &view.error {
+            format!("fetch failed: {}", err)
+        } else if self.input.is_some() {
+            "replay".to_string()
+        } else if view.paused {
+            "paused".to_string()
+        } else {
+            format!("refresh: {}s", self.refresh)
+        };
+        let mut help = if view.cursor.is_some() {
+            format!(" {} | ←/→ move cursor, esc hide cursor", status)
+        } else {
+            format!(" {} | +/- zoom, ←/→ pan, 0 live, p pause, c cursor", status)
+        };
+        if !view.variables.is_empty() {
+            help += ", v variables";
+        }
+        if matches!(data, PanelData::Range(_)) {
+            help += ", ↑/↓ legend: space hide, enter isolate, / filter, s sort";
+        }
+        help += ", q quit ";
+
+        let mut plot = None;
         terminal
             .draw(|frame| {
-                if series.is_empty() {
-                    return;
-                }
-
-                // Legend height: one line per series, plus 2 for border
-                let legend_height = (series.len() as u16 + 2).min(frame.area().height / 3);
-                let chunks =
-                    Layout::vertical([Constraint::Min(8), Constraint::Length(legend_height)])
-                        .split(frame.area());
-
-                // Compute global bounds
-                let mut x_min = f64::INFINITY;
-                let mut x_max = f64::NEG_INFINITY;
-                let mut y_min = f64::INFINITY;
-                let mut y_max = f64::NEG_INFINITY;
-
-                for s in series {
-                    for &(x, y) in &s.points {
-                        x_min = x_min.min(x);
-                        x_max = x_max.max(x);
-                        y_min = y_min.min(y);
-                        y_max = y_max.max(y);
+                let help = match view.error {
+                    Some(_) => Line::from(help).style(Style::default().fg(Color::Red)),
+                    None => Line::from(help),
+                };
+                let block = Block::bordered().title(title).title_bottom(help);
+                match data {
+                    PanelData::Range(series) => {
+                        plot = render_chart(
+                            frame,
+                            frame.area(),
+                            block,
+                            series,
+                            &mut view.legend,
+                            view.cursor,
+                            &self.options,
+                        )
+                    }
+                    PanelData::Instant(values) => {
+                        render_instant(frame, frame.area(), block, values, self.options.unit)
+                    }
+                    PanelData::Heatmap(heatmap) => {
+                        render_heatmap(frame, frame.area(), block, heatmap, self.options.unit)
                     }
                 }
-
-                // Add small padding to y bounds
-                let y_padding = (y_max - y_min).abs() * 0.05;
-                if y_padding == 0.0 {
-                    y_min -= 0.5;
-                    y_max += 0.5;
-                } else {
-                    y_min -= y_padding;
-                    y_max += y_padding;
+                if let Some(picker) = picker {
+                    render_picker(frame, picker, &view.variables);
                 }
+            })
+            .into_diagnostic()?;
+        Ok(plot)
+    }
+}
 
-                // Build datasets
-                let datasets: Vec<Dataset> = series
-                    .iter()
-                    .enumerate()
-                    .map(|(i, s)| {
-                        let (r, g, b) = COLORS[i % COLORS.len()];
-                        Dataset::default()
-                            .name(s.label.clone())
-                            .marker(Marker::Braille)
-                            .graph_type(GraphType::Line)
-                            .style(Style::default().fg(Color::Rgb(r, g, b)))
-                            .data(&s.points)
-                    })
-                    .collect();
+/// Draws a single screen for output that is not a terminal, which the interactive
+/// chart would garble.
+impl Renderer for BackendRatatui {
+    fn points(&self) -> u32 {
+        let (columns, _) = terminal::size();
+        plot_width(columns)
+    }
 
-                // Format time labels
-                let fmt_time = |ts: f64| -> String {
-                    if let Ok(t) = Timestamp::from_second(ts as i64) {
-                        let zoned = t.to_zoned(TimeZone::system());
-                        zoned.strftime("%H:%M").to_string()
-                    } else {
-                        "N/A".to_string()
-                    }
-                };
+    /// A heatmap cell is a whole column wide rather than a braille dot.
+    fn heatmap_points(&self) -> u32 {
+        self.points() / 2
+    }
 
-                let x_labels = vec![
-                    Span::raw(fmt_time(x_min)),
-                    Span::raw(fmt_time((x_min + x_max) / 2.0)),
-                    Span::raw(fmt_time(x_max)),
-                ];
-
-                let y_labels = vec![
-                    Span::raw(format!("{:.2}", y_min)),
-                    Span::raw(format!("{:.2}", (y_min + y_max) / 2.0)),
-                    Span::raw(format!("{:.2}", y_max)),
-                ];
-
-                let title = format!(
-                    " {} | refresh: {}s | press q to quit ",
-                    self.expr, self.refresh
-                );
-
-                let chart = Chart::new(datasets)
-                    .block(Block::bordered().title(title))
-                    .x_axis(
-                        Axis::default()
-                            .title("Time")
-                            .bounds([x_min, x_max])
-                            .labels(x_labels),
-                    )
-                    .y_axis(
-                        Axis::default()
-                            .title("Value")
-                            .bounds([y_min, y_max])
-                            .labels(y_labels),
-                    );
-
-                frame.render_widget(chart, chunks[0]);
-
-                // Render legend as a separate widget
-                let legend_lines: Vec<Line> = series
-                    .iter()
-                    .enumerate()
-                    .map(|(i, s)| {
-                        let (r, g, b) = COLORS[i % COLORS.len()];
-                        let color = Color::Rgb(r, g, b);
-                        let (min, max, last) = s.stats();
-                        Line::from(vec![
-                            Span::styled("■ ", Style::default().fg(color)),
-                            Span::raw(format!(
-                                "{} | min: {:.2}  max: {:.2}  last: {:.2}",
-                                s.label, min, max, last
-                            )),
-                        ])
-                    })
-                    .collect();
+    fn render(&self, title: &str, frame: &series::Frame) -> Result<String> {
+        snapshot(|f| {
+            let block = Block::bordered().title(format!(" {} ", title));
+            let mut legend = Legend::default();
+            let series = &frame.series;
+            render_chart(f, f.area(), block, series, &mut legend, None, &self.options);
+        })
+    }
 
-                let legend = Paragraph::new(legend_lines)
-                    .block(Block::bordered().title(" Legend "))
-                    .wrap(Wrap { trim: false });
+    fn render_instant(&self, title: &str, mut values: Vec<InstantValue>) -> Result<String> {
+        promql::sort_instant(&mut values);
+        snapshot(|f| {
+            let block = Block::bordered().title(format!(" {} ", title));
+            render_instant(f, f.area(), block, &values, self.options.unit);
+        })
+    }
 
-                frame.render_widget(legend, chunks[1]);
-            })
-            .into_diagnostic()?;
-        Ok(())
+    fn render_heatmap(&self, title: &str, heatmap: &Heatmap) -> Result<String> {
+        snapshot(|f| {
+            let block = Block::bordered().title(format!(" {} ", title));
+            render_heatmap(f, f.area(), block, heatmap, self.options.unit);
+        })
     }
 }
 
-fn parse_series(data: &[RangeVector]) -> Vec<SeriesData> {
-    data.iter()
-        .map(|v| {
-            let metric_name = v.metric().get("__name__").cloned().unwrap_or_default();
+/// Draws off screen at the size of the terminal, returning the characters without
+/// their colours.
+fn snapshot(draw: impl FnOnce(&mut Frame)) -> Result<String> {
+    let (columns, rows) = terminal::size();
+    let mut terminal = Terminal::new(TestBackend::new(columns, rows)).into_diagnostic()?;
+    terminal.draw(draw).into_diagnostic()?;
 
-            let label_parts: Vec<String> = v
-                .metric()
-                .iter()
-                .filter(|(key, _)| *key != "__name__")
-                .map(|(key, value)| (key.clone(), value.clone()))
-                .collect::<Vec<_>>()
-                .tap_sort()
+    let buffer = terminal.backend().buffer();
+    let lines: Vec<String> = (0..rows)
+        .map(|y| {
+            let line: String = (0..columns).map(|x| buffer[(x, y)].symbol()).collect();
+            line.trim_end().to_string()
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// The absolute window, leaving out the end's date when it is the start's.
+fn format_range(range: &TimeRange) -> String {
+    let (start, end) = range.bounds();
+    let local = |ts: i64| Timestamp::from_second(ts).map(|t| t.to_zoned(TimeZone::system()));
+    match (local(start), local(end)) {
+        (Ok(start), Ok(end)) if start.date() == end.date() => format!(
+            "{} → {}",
+            start.strftime("%Y-%m-%d %H:%M:%S"),
+            end.strftime("%H:%M:%S")
+        ),
+        (Ok(start), Ok(end)) => format!(
+            "{} → {}",
+            start.strftime("%Y-%m-%d %H:%M:%S"),
+            end.strftime("%Y-%m-%d %H:%M:%S")
+        ),
+        _ => "N/A".to_string(),
+    }
+}
+
+/// Runs the queries for one chart, sizing the step for `points` horizontal positions.
+/// Native histograms are drawn as the quantiles of `options` unless in a heatmap.
+pub(crate) async fn fetch_panel(
+    client: &Client,
+    queries: &[Query],
+    range: &TimeRange,
+    options: &ChartOptions,
+    points: u32,
+) -> Result<PanelData> {
+    match options.mode {
+        Mode::Range | Mode::Heatmap => {
+            let step = options.step_seconds(range, points);
+            let data = promql::get_data(client, queries, step, range).await?;
+            range_panel(data, options)
+        }
+        Mode::Instant => {
+            let mut data = promql::get_instant(client, queries, range).await?;
+            promql::sort_instant(&mut data);
+            Ok(PanelData::Instant(data))
+        }
+    }
+}
+
+/// Prepares range query results for drawing as a heatmap or as time series, the
+/// latter with their gaps marked.
+fn range_panel(data: Vec<(String, RangeSeries)>, options: &ChartOptions) -> Result<PanelData> {
+    match options.mode {
+        Mode::Heatmap => Ok(PanelData::Heatmap(Heatmap::from_series(&data)?)),
+        Mode::Range | Mode::Instant => {
+            let data = histogram::quantile_lines(data, &options.quantiles);
+            let mut frame = series::Frame::from_range(&data);
+            frame.mark_gaps(options.gaps);
+            Ok(PanelData::Range(frame.series))
+        }
+    }
+}
+
+/// Where the chart drew its samples, for mapping screen columns back to timestamps.
+#[derive(Clone, Copy)]
+pub(crate) struct Plot {
+    area: Rect,
+    x_min: f64,
+    x_max: f64,
+}
+
+impl Plot {
+    fn timestamp_at(&self, column: u16) -> f64 {
+        let offset = (column.saturating_sub(self.area.x) as f64 + 0.5) / self.area.width as f64;
+        self.x_min + offset.min(1.0) * (self.x_max - self.x_min)
+    }
+
+    fn column_of(&self, ts: f64) -> u16 {
+        let offset = (ts - self.x_min) / (self.x_max - self.x_min);
+        self.area.x + (offset * self.area.width.saturating_sub(1) as f64).round() as u16
+    }
+}
+
+/// Draws a time series chart with its legend below it, and a vertical line with a
+/// readout of every series at `cursor` when given. Only the series `legend` shows
+/// are drawn and scaled to, and stacked on each other for stacked charts.
+pub(crate) fn render_chart(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    series: &[Series],
+    legend: &mut Legend,
+    cursor: Option<f64>,
+    options: &ChartOptions,
+) -> Option<Plot> {
+    let (unit, style) = (options.unit, options.style);
+    if series.is_empty() {
+        frame.render_widget(Paragraph::new("No data").block(block), area);
+        return None;
+    }
+
+    // The block frames both the chart and the legend below it
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // Legend height: one line per series, plus 1 for the separator
+    let legend_height = (series.len() as u16 + 1).min(inner.height / 3);
+    let chunks =
+        Layout::vertical([Constraint::Min(8), Constraint::Length(legend_height)]).split(inner);
+
+    // Series keep their index, and so their colour, when others are hidden
+    let visible: Vec<(usize, &Series)> = series
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| legend.is_visible(s))
+        .collect();
+
+    // Compute global bounds, the time axis staying put while series are hidden
+    let mut x_min = f64::INFINITY;
+    let mut x_max = f64::NEG_INFINITY;
+
+    for s in series {
+        for &x in &s.timestamps {
+            x_min = x_min.min(x);
+            x_max = x_max.max(x);
+        }
+    }
+    // What is drawn: the runs of samples between gaps, or of running totals when
+    // stacked
+    let null_as = options.gaps.null_as;
+    let drawn: Vec<Vec<Vec<(f64, f64)>>> = match style {
+        ChartStyle::Stacked => {
+            let series: Vec<&Series> = visible.iter().map(|&(_, s)| s).collect();
+            chart_style::stack(&series, null_as)
                 .iter()
-                .filter(|(_, value)| !value.is_empty())
-                .map(|(key, value)| format!("{}={}", key, value))
-                .collect();
+                .map(Series::segments)
+                .collect()
+        }
+        ChartStyle::Steps => visible
+            .iter()
+            .map(|(_, s)| s.segments().iter().map(|p| chart_style::steps(p)).collect())
+            .collect(),
+        _ => visible.iter().map(|(_, s)| s.segments()).collect(),
+    };
+    let (y_min, y_max) = series::y_bounds(visible.iter().map(|&(_, s)| s), style, null_as);
+
+    // Build datasets, stacks from the top so the lower areas are drawn over them
+    let (graph_type, marker) = match style {
+        ChartStyle::Line | ChartStyle::Steps => (GraphType::Line, Marker::Braille),
+        ChartStyle::Points => (GraphType::Scatter, Marker::Braille),
+        ChartStyle::Area | ChartStyle::Stacked => (GraphType::Bar, Marker::Braille),
+        ChartStyle::Bars => (GraphType::Bar, Marker::HalfBlock),
+    };
+    let mut layers: Vec<(usize, &[(f64, f64)])> = visible
+        .iter()
+        .zip(&drawn)
+        .flat_map(|(&(i, _), segments)| segments.iter().map(move |p| (i, p.as_slice())))
+        .collect();
+    if style == ChartStyle::Stacked {
+        layers.reverse();
+    }
+    // NaN and infinite samples as dots on the top or bottom edge
+    let invalid: Vec<(usize, Vec<(f64, f64)>)> = visible
+        .iter()
+        .map(|&(i, s)| {
+            let mut points = s.invalid();
+            for (_, y) in &mut points {
+                *y = series::edge(*y, y_min, y_max);
+            }
+            (i, points)
+        })
+        .filter(|(_, points)| !points.is_empty())
+        .collect();
+    let mut datasets: Vec<Dataset> = layers
+        .into_iter()
+        .map(|(i, points)| {
+            let (r, g, b) = COLORS[i % COLORS.len()];
+            Dataset::default()
+                .marker(marker)
+                .graph_type(graph_type)
+                .style(Style::default().fg(Color::Rgb(r, g, b)))
+                .data(points)
+        })
+        .collect();
+    datasets.extend(invalid.iter().map(|(i, points)| {
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        Dataset::default()
+            .marker(Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(Color::Rgb(r, g, b)))
+            .data(points)
+    }));
+
+    let cursor_line = cursor.map(|x| [(x, y_min), (x, y_max)]);
+    if let Some(line) = &cursor_line {
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::White))
+                .data(line),
+        );
+    }
 
-            let label = if label_parts.is_empty() {
-                metric_name
+    let x_labels = vec![
+        Span::raw(format_time(x_min)),
+        Span::raw(format_time((x_min + x_max) / 2.0)),
+        Span::raw(format_time(x_max)),
+    ];
+
+    let y_labels = vec![
+        Span::raw(unit.format(y_min)),
+        Span::raw(unit.format((y_min + y_max) / 2.0)),
+        Span::raw(unit.format(y_max)),
+    ];
+
+    // Same arithmetic as the Chart widget's layout: labels left of the y axis take
+    // at most a third of the width, then comes the axis line
+    let label_width = y_labels
+        .iter()
+        .map(Span::width)
+        .chain(x_labels.first().map(|l| l.width().saturating_sub(1)))
+        .max()
+        .unwrap_or_default() as u16;
+    let left = label_width.min(chunks[0].width / 3) + 1;
+    let plot = Plot {
+        area: Rect {
+            x: chunks[0].x + left,
+            y: chunks[0].y,
+            width: chunks[0].width.saturating_sub(left).max(1),
+            height: chunks[0].height.saturating_sub(2),
+        },
+        x_min,
+        x_max,
+    };
+
+    let chart = Chart::new(datasets)
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .bounds([x_min, x_max])
+                .labels(x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .title("Value")
+                .bounds([y_min, y_max])
+                .labels(y_labels),
+        );
+
+    frame.render_widget(chart, chunks[0]);
+    if let Some(cursor) = cursor {
+        render_tooltip(frame, &plot, &visible, cursor, unit);
+    }
+
+    // Render legend as a separate widget
+    let items: Vec<ListItem> = legend
+        .entries(series)
+        .into_iter()
+        .map(|i| {
+            let s = &series[i];
+            let stats = s.stats();
+            let text = format!(
+                "{} | min: {}  max: {}  avg: {}  last: {}",
+                s.name,
+                unit.format(stats.min),
+                unit.format(stats.max),
+                unit.format(stats.avg),
+                unit.format(stats.last)
+            );
+            if legend.is_visible(s) {
+                let (r, g, b) = COLORS[i % COLORS.len()];
+                ListItem::new(Line::from(vec![
+                    Span::styled("■ ", Style::default().fg(Color::Rgb(r, g, b))),
+                    Span::raw(text),
+                ]))
             } else {
-                format!("{}({})", metric_name, label_parts.join(","))
+                ListItem::new(Line::from(vec![Span::raw("□ "), Span::raw(text)]))
+                    .style(Style::default().fg(Color::DarkGray))
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::new().borders(Borders::TOP).title(legend.title()))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(list, chunks[1], &mut legend.state);
+    Some(plot)
+}
+
+/// Time axis label of a sample.
+fn format_time(ts: f64) -> String {
+    match Timestamp::from_second(ts as i64) {
+        Ok(t) => t.to_zoned(TimeZone::system()).strftime("%H:%M").to_string(),
+        Err(_) => "N/A".to_string(),
+    }
+}
+
+/// Draws histogram buckets over time, each character cell showing two buckets with
+/// a half block: the upper one in the foreground, the lower one as background.
+pub(crate) fn render_heatmap(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    heatmap: &Heatmap,
+    unit: Unit,
+) {
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let labels: Vec<String> = (0..heatmap.bounds.len())
+        .map(|bucket| heatmap.bucket_label(bucket, unit))
+        .collect();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16;
+    let [chart, time_axis, scale] = Layout::vertical([
+        Constraint::Min(2),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(inner);
+    let [axis, cells] =
+        Layout::horizontal([Constraint::Length(label_width + 1), Constraint::Min(1)]).areas(chart);
+    if cells.width == 0 || cells.height == 0 || heatmap.bounds.is_empty() {
+        return;
+    }
+
+    let rows = cells.height as usize * 2;
+    let grid = heatmap.resample(cells.width as usize, rows);
+    let max = heatmap::max(&grid);
+    let rgb = |value: f64| heatmap::color(value, max).map(|(r, g, b)| Color::Rgb(r, g, b));
+
+    let buffer = frame.buffer_mut();
+    for (x, column) in grid.iter().enumerate() {
+        for y in 0..cells.height {
+            let upper = rows - 1 - 2 * y as usize;
+            let cell = &mut buffer[(cells.x + x as u16, cells.y + y)];
+            match (rgb(column[upper]), rgb(column[upper - 1])) {
+                (Some(fg), Some(bg)) => cell.set_symbol("▀").set_fg(fg).set_bg(bg),
+                (Some(fg), None) => cell.set_symbol("▀").set_fg(fg),
+                (None, Some(fg)) => cell.set_symbol("▄").set_fg(fg),
+                (None, None) => cell.set_symbol(" "),
             };
+        }
+    }
 
-            let points: Vec<(f64, f64)> = v
-                .samples()
-                .iter()
-                .map(|s| (s.timestamp(), s.value()))
-                .collect();
+    // Each row is labelled with the bound of its top bucket, once per bucket
+    let mut previous = None;
+    let axis_lines: Vec<Line> = (0..cells.height)
+        .map(|y| {
+            let upper = rows - 1 - 2 * y as usize;
+            let bucket = heatmap.buckets_in(upper, rows).end - 1;
+            if previous.replace(bucket) == Some(bucket) {
+                Line::default()
+            } else {
+                Line::from(labels[bucket].clone()).right_aligned()
+            }
+        })
+        .collect();
+    let axis = Rect {
+        width: label_width,
+        ..axis
+    };
+    frame.render_widget(Paragraph::new(axis_lines), axis);
+
+    if let (Some(&first), Some(&last)) = (heatmap.timestamps.first(), heatmap.timestamps.last()) {
+        let time_axis = Rect {
+            x: cells.x,
+            width: cells.width,
+            ..time_axis
+        };
+        frame.render_widget(Paragraph::new(format_time(first)), time_axis);
+        frame.render_widget(
+            Paragraph::new(format_time((first + last) / 2.0)).centered(),
+            time_axis,
+        );
+        frame.render_widget(Paragraph::new(format_time(last)).right_aligned(), time_axis);
+    }
+
+    let mut ramp = vec![Span::raw("0 ")];
+    ramp.extend((0..20).map(|i| {
+        let (r, g, b) = heatmap::ramp(i as f64 / 19.0);
+        Span::styled("█", Style::default().fg(Color::Rgb(r, g, b)))
+    }));
+    ramp.push(Span::raw(format!(" {} per bucket", Unit::None.format(max))));
+    frame.render_widget(Paragraph::new(Line::from(ramp)).right_aligned(), scale);
+}
+
+/// Lists every series' value at `cursor`, largest first, beside the cursor line.
+fn render_tooltip(
+    frame: &mut Frame,
+    plot: &Plot,
+    series: &[(usize, &Series)],
+    cursor: f64,
+    unit: Unit,
+) {
+    let mut values: Vec<(usize, &Series, Option<f64>)> = series
+        .iter()
+        .map(|&(i, s)| (i, s, s.value_at(cursor).filter(|&v| !series::is_stale(v))))
+        .collect();
+    values.sort_by(|a, b| match (a.2, b.2) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
 
-            SeriesData { label, points }
+    let time = Timestamp::from_second(cursor as i64)
+        .map(|t| {
+            t.to_zoned(TimeZone::system())
+                .strftime("%Y-%m-%d %H:%M:%S")
+                .to_string()
         })
-        .filter(|s| !s.points.is_empty())
-        .collect()
+        .unwrap_or_else(|_| "N/A".to_string());
+    let lines: Vec<Line> = values
+        .iter()
+        .map(|&(i, s, value)| {
+            let (r, g, b) = COLORS[i % COLORS.len()];
+            let value = value.map_or("-".to_string(), |v| unit.format(v));
+            Line::from(vec![
+                Span::styled("■ ", Style::default().fg(Color::Rgb(r, g, b))),
+                Span::raw(format!("{}: {}", s.name, value)),
+            ])
+        })
+        .collect();
+
+    let content_width = lines
+        .iter()
+        .map(Line::width)
+        .chain([time.len()])
+        .max()
+        .unwrap_or_default() as u16;
+    let width = (content_width + 2).min(plot.area.width);
+    let height = (lines.len() as u16 + 2).min(plot.area.height);
+
+    // Beside the cursor line, on whichever side has room
+    let column = plot.column_of(cursor);
+    let x = if column + 2 + width <= plot.area.right() {
+        column + 2
+    } else {
+        column.saturating_sub(width + 1).max(plot.area.x)
+    };
+    let area = Rect {
+        x,
+        y: plot.area.y,
+        width,
+        height,
+    };
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(format!(" {} ", time))),
+        area,
+    );
+}
+
+/// Distinct sample timestamps of all series, in order.
+fn timestamps(series: &[Series]) -> Vec<f64> {
+    let mut timestamps: Vec<f64> = series
+        .iter()
+        .flat_map(|s| s.timestamps.iter().copied())
+        .collect();
+    timestamps.sort_by(f64::total_cmp);
+    timestamps.dedup();
+    timestamps
 }
 
-trait TapSort {
-    fn tap_sort(&mut self) -> &Self;
+/// The sample timestamp nearest to `ts`.
+fn nearest(series: &[Series], ts: f64) -> Option<f64> {
+    timestamps(series)
+        .into_iter()
+        .min_by(|a, b| (a - ts).abs().total_cmp(&(b - ts).abs()))
 }
 
-impl<T: Ord> TapSort for Vec<T> {
-    fn tap_sort(&mut self) -> &Self {
-        self.sort();
-        self
+/// Draws the picker as a list centered over the chart.
+fn render_picker(frame: &mut Frame, picker: &mut Picker, variables: &[Variable]) {
+    let [area] = Layout::horizontal([Constraint::Percentage(40)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [area] = Layout::vertical([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(Clear, area);
+
+    let title = match picker.variable {
+        Some(index) => format!(" {} ", variables[index].name),
+        None => " Variables ".to_string(),
+    };
+    let block = Block::bordered()
+        .title(title)
+        .title_bottom(format!(" filter: {} ", picker.filter))
+        .border_style(Style::default().fg(Color::Yellow));
+
+    if let Some(error) = &picker.error {
+        let paragraph = Paragraph::new(error.as_str())
+            .style(Style::default().fg(Color::Red))
+            .wrap(Wrap { trim: false })
+            .block(block);
+        frame.render_widget(paragraph, area);
+        return;
     }
+
+    let items: Vec<String> = picker
+        .visible()
+        .into_iter()
+        .map(|item| match picker.variable {
+            Some(_) => item.clone(),
+            None => {
+                let value = variables
+                    .iter()
+                    .find(|v| &v.name == item)
+                    .and_then(|v| v.value.as_deref())
+                    .unwrap_or_default();
+                format!("{} = {}", item, value)
+            }
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, area, &mut picker.state);
+}
+
+/// Ratios in `0..=1` are drawn as gauges, anything else as horizontal bars.
+pub(crate) fn render_instant(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    values: &[InstantValue],
+    unit: Unit,
+) {
+    let is_ratio = values.iter().all(|v| (0.0..=1.0).contains(&v.value));
+    if is_ratio {
+        render_gauges(frame, area, block, values, 1.0, unit);
+    } else {
+        render_bars(frame, area, block, values, unit);
+    }
+}
+
+/// Draws one gauge per value, filled relative to `max`.
+pub(crate) fn render_gauges(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    values: &[InstantValue],
+    max: f64,
+    unit: Unit,
+) {
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); values.len()]).split(inner);
+    for (i, (v, row)) in values.iter().zip(rows.iter()).enumerate() {
+        let (r, g, b) = COLORS[i % COLORS.len()];
+        let ratio = if max > 0.0 { v.value / max } else { 0.0 };
+        let gauge = LineGauge::default()
+            .label(format!("{} {}", v.label, unit.format(v.value)))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .filled_style(Style::default().fg(Color::Rgb(r, g, b)));
+        frame.render_widget(gauge, *row);
+    }
+}
+
+/// Draws values as horizontal bars, largest magnitude filling the width.
+pub(crate) fn render_bars(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    values: &[InstantValue],
+    unit: Unit,
+) {
+    // BarChart only takes integers, so scale values and show the real one as text
+    let max = values.iter().map(|v| v.value.abs()).fold(0.0, f64::max);
+    let scale = if max > 0.0 { 10_000.0 / max } else { 0.0 };
+
+    let bars: Vec<Bar> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let (r, g, b) = COLORS[i % COLORS.len()];
+            Bar::with_label(v.label.clone(), (v.value.abs() * scale).round() as u64)
+                .text_value(unit.format(v.value))
+                .style(Style::default().fg(Color::Rgb(r, g, b)))
+        })
+        .collect();
+
+    let chart = BarChart::horizontal(bars)
+        .block(block)
+        .bar_width(1)
+        .bar_gap(0);
+
+    frame.render_widget(chart, area);
+}
+
+/// Braille resolution of a chart `columns` wide: two dots per column, minus borders and
+/// y-axis labels.
+pub(crate) fn plot_width(columns: u16) -> u32 {
+    columns.saturating_sub(12) as u32 * 2
 }
@@ -0,0 +1,190 @@
+use clap::ValueEnum;
+use miette::{Result, miette};
+
+use crate::{
+    heatmap::Heatmap,
+    promql::InstantValue,
+    series::{Frame, Renderer, Series, Stats},
+    terminal,
+    units::Unit,
+};
+
+/// Blocks from the lowest value of a series to its highest.
+const BLOCKS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Width of each statistic column.
+const STAT_WIDTH: usize = 10;
+
+/// Narrowest sparkline, however long the labels.
+const MIN_WIDTH: usize = 10;
+
+/// Share of the columns labels may take before they are shortened.
+const LABEL_SHARE: usize = 3;
+
+/// Order of the series, by name or by one of their statistics, largest first.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SortBy {
+    Name,
+    Min,
+    Max,
+    Avg,
+    Last,
+}
+
+impl SortBy {
+    fn key(self, stats: &Stats) -> f64 {
+        match self {
+            SortBy::Name => 0.0,
+            SortBy::Min => stats.min,
+            SortBy::Max => stats.max,
+            SortBy::Avg => stats.avg,
+            SortBy::Last => stats.last,
+        }
+    }
+}
+
+pub struct BackendSparkline {
+    /// Width of a line in characters, label and statistics included
+    columns: u16,
+    unit: Unit,
+    /// Series are listed in query order without one
+    sort: Option<SortBy>,
+}
+
+impl BackendSparkline {
+    pub fn new(columns: u16, unit: Unit, sort: Option<SortBy>) -> Self {
+        Self {
+            columns,
+            unit,
+            sort,
+        }
+    }
+
+    /// Widest labels, a share of the line.
+    fn max_label_width(&self) -> usize {
+        (self.columns as usize / LABEL_SHARE).max("SERIES".len())
+    }
+
+    /// Characters left for the sparkline beside labels `label_width` wide and the
+    /// statistics.
+    fn sparkline_width(&self, label_width: usize) -> usize {
+        (self.columns as usize)
+            .saturating_sub(label_width + 2 + 4 * (STAT_WIDTH + 2))
+            .max(MIN_WIDTH)
+    }
+}
+
+impl Renderer for BackendSparkline {
+    /// A sample per character of the sparkline beside the widest labels.
+    fn points(&self) -> u32 {
+        self.sparkline_width(self.max_label_width()) as u32
+    }
+
+    /// A line per series: its name, its values over time as blocks scaled from its
+    /// minimum to its maximum, then its statistics.
+    fn render(&self, _title: &str, frame: &Frame) -> Result<String> {
+        if frame.series.is_empty() {
+            return Ok("No data".to_string());
+        }
+
+        let mut rows: Vec<(&Series, Stats)> = frame.series.iter().map(|s| (s, s.stats())).collect();
+        match self.sort {
+            Some(SortBy::Name) => rows.sort_by(|a, b| a.0.name.cmp(&b.0.name)),
+            Some(sort) => rows.sort_by(|a, b| sort.key(&b.1).total_cmp(&sort.key(&a.1))),
+            None => {}
+        }
+
+        let label_width = rows
+            .iter()
+            .map(|(s, _)| s.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .clamp("SERIES".len(), self.max_label_width());
+        let width = self.sparkline_width(label_width);
+        // No more blocks than samples, or the blocks without one would look like gaps
+        let mut timestamps: Vec<f64> = frame
+            .series
+            .iter()
+            .flat_map(|s| s.timestamps.iter().copied())
+            .collect();
+        timestamps.sort_by(f64::total_cmp);
+        timestamps.dedup();
+        let blocks = width.min(timestamps.len());
+        // Every sparkline spans the same time, so they line up
+        let (start, end) = (timestamps[0], timestamps[timestamps.len() - 1]);
+
+        let line = |label: &str, spark: &str, stats: [String; 4]| {
+            let stats = stats.map(|stat| format!("  {stat:>STAT_WIDTH$}")).concat();
+            format!("{label:<label_width$}  {spark:<width$}{stats}")
+        };
+        let mut lines = vec![line(
+            "SERIES",
+            "",
+            ["MIN", "AVG", "MAX", "LAST"].map(String::from),
+        )];
+        for (series, stats) in rows {
+            let label = terminal::truncate(&series.name, label_width);
+            let spark = sparkline(series, start, end, blocks);
+            let stats = [stats.min, stats.avg, stats.max, stats.last].map(|v| self.unit.format(v));
+            lines.push(line(&label, &spark, stats));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn render_instant(&self, _title: &str, _values: Vec<InstantValue>) -> Result<String> {
+        Err(miette!(
+            help = "use the plain, textplots or ratatui backend",
+            "instant queries are not supported by the sparkline backend"
+        ))
+    }
+
+    fn render_heatmap(&self, _title: &str, _heatmap: &Heatmap) -> Result<String> {
+        Err(miette!(
+            help = "use the textplots, plotters or ratatui backend",
+            "heatmaps are not supported by the sparkline backend"
+        ))
+    }
+}
+
+/// `width` blocks for the series between `start` and `end`, each the average of
+/// the samples in its slice of time. Slices without a finite sample stay blank,
+/// so gaps show.
+fn sparkline(series: &Series, start: f64, end: f64, width: usize) -> String {
+    let mut sums = vec![(0.0, 0); width];
+    let span = (end - start).max(1.0);
+    for (&x, &y) in series.timestamps.iter().zip(&series.values) {
+        if y.is_finite() {
+            let slot = ((x - start) / span * (width - 1) as f64).round() as usize;
+            let (sum, count) = &mut sums[slot.min(width - 1)];
+            *sum += y;
+            *count += 1;
+        }
+    }
+
+    let averages: Vec<Option<f64>> = sums
+        .iter()
+        .map(|&(sum, count)| (count > 0).then(|| sum / count as f64))
+        .collect();
+    let min = averages
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let max = averages
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    averages
+        .iter()
+        .map(|average| match average {
+            // A flat series sits on the bottom rather than dividing by zero
+            Some(value) if max > min => {
+                let level = (value - min) / (max - min) * (BLOCKS.len() - 1) as f64;
+                BLOCKS[level.round() as usize]
+            }
+            Some(_) => BLOCKS[0],
+            None => ' ',
+        })
+        .collect()
+}
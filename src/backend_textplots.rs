@@ -1,250 +1,445 @@
+use std::fmt::Display;
+
 use jiff::{Timestamp, tz::TimeZone};
 use miette::Result;
 use owo_colors::{OwoColorize, Rgb};
-use prometheus_http_query::response::RangeVector;
 use rgb::RGB8;
-use textplots::{
-    Chart, ColorPlot, LabelBuilder, LabelFormat, Shape, TickDisplay, TickDisplayBuilder,
+use textplots::{Chart, ColorPlot, Shape};
+
+use crate::{
+    heatmap::{self, Heatmap},
+    promql::{self, InstantValue},
+    series::{self, Frame, Renderer, Series},
+    style::{self, ChartStyle},
+    terminal,
+    units::Unit,
 };
 
+// Define colors for different series
+const COLORS: &[RGB8] = &[
+    RGB8::new(0, 252, 0),   // Green
+    RGB8::new(252, 0, 0),   // Red
+    RGB8::new(252, 252, 0), // Yellow
+    RGB8::new(252, 0, 252), // Magenta
+    RGB8::new(0, 252, 252), // Cyan
+    RGB8::new(252, 165, 0), // Orange
+    RGB8::new(128, 0, 128), // Purple
+    RGB8::new(0, 0, 252),   // Blue
+];
+
+/// Samples as `(timestamp, value)` pairs.
+type Points = Vec<(f64, f64)>;
+
+/// Lines around a time series chart besides a line per series: the one introducing
+/// them, the time axis and the shell prompt after it.
+const CHART_EXTRA_LINES: usize = 3;
+
+/// Lines below a heatmap: the time axis, the colour ramp and the shell prompt.
+const HEATMAP_EXTRA_LINES: usize = 3;
+
+/// Rows between value labels, as textplots' sparse ticks.
+const LABEL_ROWS: usize = 4;
+
+/// Narrowest chart in characters, textplots needs 32 braille dots.
+const MIN_COLUMNS: usize = 17;
+
 pub struct BackendTextplots {
-    width: u32,
-    height: u32,
+    /// Size of the output in characters, labels and legend included
+    columns: u16,
+    rows: u16,
+    unit: Unit,
+    style: ChartStyle,
+    /// Whether to colour the output with ANSI escape codes
+    color: bool,
 }
 
 impl BackendTextplots {
-    pub fn new(width: u32, height: u32) -> Self {
-        Self { width, height }
+    pub fn new(columns: u16, rows: u16, unit: Unit, style: ChartStyle, color: bool) -> Self {
+        Self {
+            columns,
+            rows,
+            unit,
+            style,
+            color,
+        }
     }
-}
 
-fn get_bounds(points: &[(f64, f64)]) -> (f64, f64, f64, f64) {
-    if points.is_empty() {
-        return (0.0, 0.0, 0.0, 0.0);
+    /// `text` in the colour, unless colours are off.
+    fn paint(&self, text: impl Display, (r, g, b): (u8, u8, u8)) -> String {
+        if self.color {
+            text.color(Rgb(r, g, b)).to_string()
+        } else {
+            text.to_string()
+        }
     }
+}
 
-    let mut xmin = points[0].0;
-    let mut xmax = points[0].0;
-    let mut ymin = points[0].1;
-    let mut ymax = points[0].1;
-
-    for &(x, y) in points.iter() {
-        xmin = xmin.min(x);
-        xmax = xmax.max(x);
-        ymin = ymin.min(y);
-        ymax = ymax.max(y);
+impl Renderer for BackendTextplots {
+    /// Horizontal resolution of the chart in braille dots, two per column.
+    fn points(&self) -> u32 {
+        u32::from(self.columns) * 2
     }
 
-    (xmin, xmax, ymin, ymax)
-}
+    /// Horizontal resolution of heatmaps, which take a character per cell.
+    fn heatmap_points(&self) -> u32 {
+        u32::from(self.columns)
+    }
 
-impl BackendTextplots {
-    pub fn generate(&self, data: Vec<RangeVector>) -> Result<String> {
-        if data.is_empty() {
+    fn render(&self, _title: &str, frame: &Frame) -> Result<String> {
+        if frame.series.is_empty() {
             return Ok("No data".to_string());
         }
 
-        // Collect all series data and find global bounds
-        let mut all_series: Vec<(String, Vec<(f64, f64)>)> = Vec::new();
-        let mut global_ymin = f64::INFINITY;
-        let mut global_ymax = f64::NEG_INFINITY;
-        let mut global_time_min = f64::INFINITY;
-        let mut global_time_max = f64::NEG_INFINITY;
-
-        for v in data.iter() {
-            let metric_name = v.metric().get("__name__").cloned().unwrap_or_default();
-
-            // Create a label for this series by finding distinguishing labels
-            let mut label_parts = Vec::new();
+        // Collect the runs of samples between gaps, or of running totals when stacked
+        let mut all_series: Vec<(String, Vec<Points>, Points)> = frame
+            .series
+            .iter()
+            .map(|s| (s.name.clone(), s.segments(), s.invalid()))
+            .collect();
+        if self.style == ChartStyle::Stacked {
+            let series: Vec<&Series> = frame.series.iter().collect();
+            let stacked = style::stack(&series, frame.null_as);
+            for ((_, segments, _), stacked) in all_series.iter_mut().zip(stacked) {
+                *segments = stacked.segments();
+            }
+        }
+        let (ymin, ymax) = frame.y_bounds(self.style);
 
-            // Get all metric labels except __name__
-            let mut metric_labels: Vec<(String, String)> = v
-                .metric()
-                .iter()
-                .filter(|(key, _)| *key != "__name__")
-                .map(|(key, value)| (key.clone(), value.clone()))
-                .collect();
+        let timestamps = frame
+            .series
+            .iter()
+            .flat_map(|s| s.timestamps.iter().copied());
+        let mut global_time_min = timestamps.clone().fold(f64::INFINITY, f64::min);
+        let mut global_time_max = timestamps.fold(f64::NEG_INFINITY, f64::max);
+
+        // The chart takes what the legend above it, the value labels right of it and
+        // the time axis below it leave of the terminal
+        let unit = self.unit;
+        let chart_rows = (self.rows as usize)
+            .saturating_sub(all_series.len() + CHART_EXTRA_LINES)
+            .max(3);
+        let labels: Vec<String> = (0..chart_rows)
+            .map(|row| {
+                if row % LABEL_ROWS == 0 || row == chart_rows - 1 {
+                    unit.format(ymax - (ymax - ymin) * row as f64 / (chart_rows - 1) as f64)
+                } else {
+                    String::new()
+                }
+            })
+            .collect();
+        let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let chart_columns = (self.columns as usize)
+            .saturating_sub(label_width + 1)
+            .max(MIN_COLUMNS);
+
+        // A canvas of w by h dots is w/2 + 1 characters wide and h/4 + 1 high. x is
+        // seconds since the start of the queried range, which f32 holds exactly enough, so
+        // series with different timestamps line up.
+        if let Some((start, end)) = frame.range {
+            global_time_min = global_time_min.min(start);
+            global_time_max = global_time_max.max(end);
+        }
+        let time_span = (global_time_max - global_time_min).max(1.0);
+        let mut chart = Chart::new_with_y_range(
+            (chart_columns as u32 - 1) * 2,
+            (chart_rows as u32 - 1) * 4,
+            0.0,
+            time_span as f32,
+            ymin as f32,
+            ymax as f32,
+        );
 
-            // Sort for consistent ordering
-            metric_labels.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut lines = vec![format!("Plotting {} series:", all_series.len())];
+        let columns = self.columns as usize;
 
-            // Build label string from all available labels
-            for (key, value) in metric_labels {
-                if !value.is_empty() {
-                    label_parts.push(format!("{}={}", key, value));
-                }
-            }
+        // Samples in chart coordinates, a shape per run of samples so that lines
+        // break at gaps, then NaN and infinite samples as points on the edges
+        let to_chart = |&(t, y): &(f64, f64)| ((t - global_time_min) as f32, y as f32);
+        let mut chart_points: Vec<(RGB8, Vec<(f32, f32)>)> = Vec::new();
+        let mut edge_points: Vec<(RGB8, Vec<(f32, f32)>)> = Vec::new();
 
-            let series_label = if label_parts.is_empty() {
-                metric_name
+        for (i, (series_label, segments, invalid)) in all_series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let count: usize = segments.iter().map(Vec::len).sum();
+            let invalid_note = if invalid.is_empty() {
+                String::new()
             } else {
-                format!("{}({})", metric_name, label_parts.join(","))
+                format!(", {} NaN or infinite on the edges", invalid.len())
             };
+            // A line per series, however long its label, or the chart would not fit
+            let details = format!(": {} points{}", count, invalid_note);
+            let label_width = columns.saturating_sub(2 + details.chars().count());
+            let line = format!(
+                "- {}{}",
+                terminal::truncate(series_label, label_width),
+                details
+            );
+            lines.push(self.paint(
+                terminal::truncate(&line, columns),
+                (color.r, color.g, color.b),
+            ));
 
-            let points: Vec<(f64, f64)> = v
-                .samples()
-                .into_iter()
-                .map(|s| (s.timestamp(), s.value()))
-                .collect();
-
-            if !points.is_empty() {
-                let (xmin, xmax, ymin, ymax) = get_bounds(&points);
-                global_ymin = global_ymin.min(ymin);
-                global_ymax = global_ymax.max(ymax);
-                global_time_min = global_time_min.min(xmin);
-                global_time_max = global_time_max.max(xmax);
-
-                all_series.push((series_label, points));
+            for segment in segments {
+                chart_points.push((color, segment.iter().map(to_chart).collect()));
+            }
+            if !invalid.is_empty() {
+                let edges = invalid
+                    .iter()
+                    .map(|&(t, y)| to_chart(&(t, series::edge(y, ymin, ymax))));
+                edge_points.push((color, edges.collect()));
             }
         }
 
-        if all_series.is_empty() {
-            return Ok("No valid data points".to_string());
+        let mut shapes_and_colors: Vec<(Shape, RGB8)> = Vec::new();
+        for (color, points) in &chart_points {
+            // textplots cannot fill, so areas are drawn as bars down to zero. A lone
+            // sample between gaps makes no line, so it is drawn as a point.
+            let shape = match self.style {
+                _ if points.len() == 1 => Shape::Points(points),
+                ChartStyle::Points => Shape::Points(points),
+                ChartStyle::Steps => Shape::Steps(points),
+                ChartStyle::Area | ChartStyle::Stacked | ChartStyle::Bars => Shape::Bars(points),
+                ChartStyle::Line => Shape::Lines(points),
+            };
+            shapes_and_colors.push((shape, *color));
         }
 
-        // Define colors for different series
-        let colors = [
-            RGB8::new(0, 252, 0),   // Green
-            RGB8::new(252, 0, 0),   // Red
-            RGB8::new(252, 252, 0), // Yellow
-            RGB8::new(252, 0, 252), // Magenta
-            RGB8::new(0, 252, 252), // Cyan
-            RGB8::new(252, 165, 0), // Orange
-            RGB8::new(128, 0, 128), // Purple
-            RGB8::new(0, 0, 252),   // Blue
-        ];
+        // Plot all series on the chart
+        // Stacks are drawn from the top so the lower series stay visible
+        if self.style == ChartStyle::Stacked {
+            shapes_and_colors.reverse();
+        }
+        for (color, points) in &edge_points {
+            shapes_and_colors.push((Shape::Points(points), *color));
+        }
+        let mut chart_ptr = &mut chart;
+        for (shape, color) in shapes_and_colors.iter() {
+            chart_ptr = chart_ptr.linecolorplot(shape, *color);
+        }
 
-        // Find the max number of points for consistent X-axis
-        let max_points = all_series
-            .iter()
-            .map(|(_, points)| points.len())
-            .max()
-            .unwrap_or(0);
+        chart_ptr.axis();
+        chart_ptr.figures();
 
-        // Create single chart
-        let mut chart = Chart::new_with_y_range(
-            self.width,
-            self.height,
-            0.0,
-            max_points as f32,
-            (global_ymin - 0.01) as f32,
-            (global_ymax + 0.01) as f32,
-        );
+        // The canvas colours through the colored crate, which otherwise decides by
+        // itself from the environment
+        colored::control::set_override(self.color);
 
-        println!("Plotting {} series:", all_series.len());
+        // textplots labels the x axis at its ends only, and rounds the height to fit
+        // its value labels, so both axes are labelled here. Blank braille rather than
+        // spaces keeps terminals from trimming the rows.
+        let canvas = chart_ptr.frame().replace(' ', "\u{2800}");
+        for (row, label) in canvas.lines().zip(&labels) {
+            lines.push(format!("{} {}", row, label).trim_end().to_string());
+        }
+        lines.push(time_axis(
+            global_time_min,
+            global_time_min + time_span,
+            chart_columns,
+        ));
 
-        // Prepare data for plotting - clone everything needed for closures upfront
-        let mut shapes_and_colors: Vec<(Shape, RGB8)> = Vec::new();
+        Ok(lines.join("\n"))
+    }
 
-        for (i, (series_label, points)) in all_series.iter().enumerate() {
-            let color = colors[i % colors.len()];
-            let points_clone = points.clone();
-
-            let owo_color = Rgb(color.r, color.g, color.b);
-
-            println!(
-                "{}",
-                format!(
-                    "- {}: {} points (color: RGB({}, {}, {}))",
-                    series_label,
-                    points.len(),
-                    color.r,
-                    color.g,
-                    color.b
-                )
-                .color(owo_color)
-            );
+    /// Renders instant query results as a horizontal bar chart, largest value first.
+    fn render_instant(&self, _title: &str, mut data: Vec<InstantValue>) -> Result<String> {
+        if data.is_empty() {
+            return Ok("No data".to_string());
+        }
 
-            let shape = Shape::Continuous(Box::new(move |x| {
-                let chart_idx = x as usize;
+        promql::sort_instant(&mut data);
 
-                if points_clone.is_empty() || chart_idx >= max_points {
-                    return 0.0;
-                }
+        let labels: Vec<String> = data.iter().map(|v| v.label.clone()).collect();
+        let values: Vec<String> = data.iter().map(|v| self.unit.format(v.value)).collect();
+        let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let value_width = values.iter().map(|v| v.chars().count()).max().unwrap_or(0);
 
-                // Map chart index to this series' time range
-                let time_progress = chart_idx as f64 / (max_points - 1).max(1) as f64;
-                let target_time =
-                    global_time_min + time_progress * (global_time_max - global_time_min);
+        let bar_width = (self.columns as usize)
+            .saturating_sub(label_width + value_width + 2)
+            .max(10);
 
-                // Find the closest data points for interpolation
-                let mut left_idx = None;
-                let mut right_idx = None;
+        // Bar length is proportional to the magnitude of the value
+        let max = data.iter().map(|v| v.value.abs()).fold(0.0, f64::max);
 
-                for (idx, &(time, _)) in points_clone.iter().enumerate() {
-                    if time <= target_time {
-                        left_idx = Some(idx);
-                    }
-                    if time >= target_time && right_idx.is_none() {
-                        right_idx = Some(idx);
-                        break;
-                    }
-                }
+        let mut lines = Vec::new();
+        for (i, ((label, value), v)) in labels
+            .iter()
+            .zip(values.iter())
+            .zip(data.iter())
+            .enumerate()
+        {
+            let color = COLORS[i % COLORS.len()];
+            let len = if max > 0.0 && v.value.is_finite() {
+                ((v.value.abs() / max) * bar_width as f64).round() as usize
+            } else {
+                0
+            };
+            let bar = "█".repeat(len);
+            lines.push(format!(
+                "{:<label_width$} {} {:>value_width$}",
+                label,
+                self.paint(format!("{:<bar_width$}", bar), (color.r, color.g, color.b)),
+                value
+            ));
+        }
 
-                match (left_idx, right_idx) {
-                    (Some(left), Some(right)) if left == right => {
-                        // Exact match
-                        points_clone[left].1 as f32
-                    }
-                    (Some(left), Some(right)) => {
-                        // Linear interpolation
-                        let (t1, y1) = points_clone[left];
-                        let (t2, y2) = points_clone[right];
-                        if t2 > t1 {
-                            let ratio = (target_time - t1) / (t2 - t1);
-                            (y1 + ratio * (y2 - y1)) as f32
-                        } else {
-                            y1 as f32
-                        }
-                    }
-                    (Some(left), None) => {
-                        // Use the last available point
-                        points_clone[left].1 as f32
-                    }
-                    (None, Some(right)) => {
-                        // Use the first available point
-                        points_clone[right].1 as f32
-                    }
-                    (None, None) => 0.0,
-                }
-            }));
+        Ok(lines.join("\n"))
+    }
 
-            shapes_and_colors.push((shape, color));
+    /// Renders histogram buckets over time, a character per cell shaded and coloured
+    /// by the number of observations.
+    fn render_heatmap(&self, _title: &str, heatmap: &Heatmap) -> Result<String> {
+        if heatmap.bounds.is_empty() {
+            return Ok("No data".to_string());
         }
-
-        // Plot all series on the chart
-        let mut chart_ptr = &mut chart;
-        for (shape, color) in shapes_and_colors.iter() {
-            chart_ptr = chart_ptr.linecolorplot(shape, *color);
+        let labels: Vec<String> = (0..heatmap.bounds.len())
+            .map(|bucket| heatmap.bucket_label(bucket, self.unit))
+            .collect();
+        let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+        let columns = (self.heatmap_points() as usize)
+            .saturating_sub(label_width + 1)
+            .max(10);
+        let rows = (self.rows as usize)
+            .saturating_sub(HEATMAP_EXTRA_LINES)
+            .max(2);
+        let grid = heatmap.resample(columns, rows);
+        let max = heatmap::max(&grid);
+
+        let mut lines = Vec::new();
+        let mut previous = None;
+        for row in (0..rows).rev() {
+            // Each row is labelled with the bound of its top bucket, once per bucket
+            let bucket = heatmap.buckets_in(row, rows).end - 1;
+            let label = if previous.replace(bucket) == Some(bucket) {
+                ""
+            } else {
+                labels[bucket].as_str()
+            };
+            let cells: String = grid
+                .iter()
+                .map(|column| match heatmap::color(column[row], max) {
+                    Some(color) => {
+                        let fraction = (column[row] / max).sqrt();
+                        let shade =
+                            ((fraction * SHADES.len() as f64) as usize).min(SHADES.len() - 1);
+                        self.paint(SHADES[shade], color)
+                    }
+                    None => " ".to_string(),
+                })
+                .collect();
+            lines.push(format!("{:>label_width$} {}", label, cells));
         }
 
-        // Use the series with the most points for time labels
-        if let Some((_, most_detailed_points)) =
-            all_series.iter().max_by_key(|(_, points)| points.len())
+        if let (Some(&first), Some(&last)) = (heatmap.timestamps.first(), heatmap.timestamps.last())
         {
-            let points_clone = most_detailed_points.clone();
-            chart_ptr
-                .x_label_format(LabelFormat::Custom(Box::new(move |val| {
-                    let idx = val as usize;
-                    if idx < points_clone.len() {
-                        let ts = Timestamp::from_second(points_clone[idx].0 as i64).unwrap();
-                        let zoned = ts.to_zoned(TimeZone::system());
-                        zoned.strftime("%H:%M").to_string()
-                    } else if !points_clone.is_empty() {
-                        let ts =
-                            Timestamp::from_second(points_clone[points_clone.len() - 1].0 as i64)
-                                .unwrap();
-                        let zoned = ts.to_zoned(TimeZone::system());
-                        zoned.strftime("%H:%M").to_string()
-                    } else {
-                        "N/A".to_string()
-                    }
-                })))
-                .y_label_format(LabelFormat::Value)
-                .y_tick_display(TickDisplay::Sparse)
-                .display();
+            lines.push(format!(
+                "{:label_width$} {}",
+                "",
+                time_axis(first, last, columns)
+            ));
         }
 
-        Ok(format!("Displayed {} series", all_series.len()))
+        let ramp: String = (0..20)
+            .map(|i| {
+                self.paint(
+                    SHADES[i * SHADES.len() / 20],
+                    heatmap::ramp(i as f64 / 19.0),
+                )
+            })
+            .collect();
+        lines.push(format!(
+            "{:label_width$} 0 {} {} per bucket",
+            "",
+            ramp,
+            Unit::None.format(max)
+        ));
+
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Shades from few observations to many, readable without colours too.
+const SHADES: &[char] = &['░', '▒', '▓', '█'];
+
+/// Spacings of time ticks in seconds, the shortest that leaves room between the
+/// labels is used.
+const TICK_INTERVALS: &[i64] = &[
+    1, 2, 5, 10, 15, 30, 60, 120, 300, 600, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400,
+    172800, 604800,
+];
+
+/// How ticks `interval` seconds apart over `span` seconds are labelled: with seconds
+/// when they are less than a minute apart, with dates when the range spans days.
+fn tick_format(span: f64, interval: i64) -> &'static str {
+    if span > 86400.0 {
+        "%m-%d %H:%M"
+    } else if interval < 60 {
+        "%H:%M:%S"
+    } else {
+        "%H:%M"
+    }
+}
+
+/// A line of time labels for an axis `columns` characters wide from `start` to `end`,
+/// at evenly spaced round local times.
+fn time_axis(start: f64, end: f64, columns: usize) -> String {
+    let span = (end - start).max(1.0);
+    let Ok(first) = Timestamp::from_second(start as i64) else {
+        return String::new();
+    };
+    let tz = TimeZone::system();
+    let format = |ts: f64, format: &str| match Timestamp::from_second(ts as i64) {
+        Ok(t) => t.to_zoned(tz.clone()).strftime(format).to_string(),
+        Err(_) => String::new(),
+    };
+
+    // Ticks fall on multiples of the interval in local time, like midnight, each
+    // labelled centered on it. Ticks too near an end for their label are left out.
+    let offset = tz.to_offset(first).seconds() as i64;
+    let ticks = |interval: i64, tick_format: &str| {
+        let local_start = start.ceil() as i64 + offset;
+        let mut tick = (local_start + interval - 1).div_euclid(interval) * interval - offset;
+        let mut ticks = Vec::new();
+        while tick as f64 <= end {
+            let label: Vec<char> = format(tick as f64, tick_format).chars().collect();
+            let column = ((tick as f64 - start) / span * columns.saturating_sub(1) as f64).round();
+            if let Some(left) = (column as usize).checked_sub(label.len() / 2)
+                && left + label.len() <= columns
+            {
+                ticks.push((left, label));
+            }
+            tick += interval;
+        }
+        ticks
+    };
+    let fits = |ticks: &[(usize, Vec<char>)]| {
+        ticks
+            .windows(2)
+            .all(|pair| pair[0].0 + pair[0].1.len() + 2 <= pair[1].0)
+    };
+
+    // The most ticks whose labels all fit with two spaces between them, rather than
+    // leaving out those that collide, so they stay evenly spaced. Intervals with more
+    // ticks than columns are not worth trying. Too narrow for two labels, one is
+    // shown.
+    let candidates = TICK_INTERVALS
+        .iter()
+        .filter(|&&interval| span / interval as f64 <= columns as f64)
+        .map(|&interval| ticks(interval, tick_format(span, interval)));
+    let ticks = candidates
+        .clone()
+        .find(|ticks| ticks.len() > 1 && fits(ticks))
+        .or_else(|| {
+            candidates
+                .rev()
+                .find_map(|ticks| ticks.into_iter().next())
+                .map(|tick| vec![tick])
+        })
+        .unwrap_or_default();
+
+    let mut line = vec![' '; columns];
+    for (left, label) in ticks {
+        line[left..left + label.len()].copy_from_slice(&label);
     }
+    line.into_iter().collect::<String>().trim_end().to_string()
 }
@@ -0,0 +1,194 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use prometheus_http_query::Client;
+use reqwest::{
+    Certificate, Identity,
+    header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::Deserialize;
+
+/// Read when `--basic-auth` is given without a password and no password file.
+const PASSWORD_ENV: &str = "PROMEGRAPH_BASIC_AUTH_PASSWORD";
+
+/// Authentication and TLS options for the Prometheus HTTP client.
+#[derive(clap::Args, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ClientArgs {
+    /// Bearer token sent in the Authorization header
+    #[arg(long, conflicts_with_all = ["bearer_token_file", "basic_auth"])]
+    pub bearer_token: Option<String>,
+
+    /// File containing the bearer token
+    #[arg(long, conflicts_with = "basic_auth")]
+    pub bearer_token_file: Option<PathBuf>,
+
+    /// Basic auth as user:pass, or just user to read the password from
+    /// --basic-auth-password-file or $PROMEGRAPH_BASIC_AUTH_PASSWORD
+    #[arg(long)]
+    pub basic_auth: Option<String>,
+
+    /// File containing the basic auth password
+    #[arg(long, requires = "basic_auth")]
+    pub basic_auth_password_file: Option<PathBuf>,
+
+    /// Extra request header as K=V, can be repeated
+    #[arg(long = "header", value_name = "K=V")]
+    pub headers: Vec<String>,
+
+    /// PEM CA certificate used to verify the server
+    #[arg(long)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate for mTLS
+    #[arg(long, requires = "client_key")]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM PKCS#8 private key for the client certificate
+    #[arg(long, requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// Accept invalid server certificates
+    #[arg(long)]
+    pub insecure_skip_verify: bool,
+}
+
+impl ClientArgs {
+    /// Fills in options missing from these arguments with the ones from `fallback`.
+    /// Credentials are never mixed: any credential given here replaces all of `fallback`'s.
+    pub fn or(mut self, fallback: &ClientArgs) -> Self {
+        let has_credentials = self.bearer_token.is_some()
+            || self.bearer_token_file.is_some()
+            || self.basic_auth.is_some();
+        if !has_credentials {
+            self.bearer_token = fallback.bearer_token.clone();
+            self.bearer_token_file = fallback.bearer_token_file.clone();
+            self.basic_auth = fallback.basic_auth.clone();
+            self.basic_auth_password_file = fallback.basic_auth_password_file.clone();
+        }
+
+        // Later headers win, so the command line ones go last
+        self.headers = fallback
+            .headers
+            .iter()
+            .chain(self.headers.iter())
+            .cloned()
+            .collect();
+
+        self.ca_cert = self.ca_cert.or_else(|| fallback.ca_cert.clone());
+        if self.client_cert.is_none() {
+            self.client_cert = fallback.client_cert.clone();
+            self.client_key = fallback.client_key.clone();
+        }
+        self.insecure_skip_verify |= fallback.insecure_skip_verify;
+        self
+    }
+}
+
+pub fn build_client(addr: &str, args: &ClientArgs) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+
+    for header in &args.headers {
+        let (name, value) = header.split_once('=').ok_or_else(|| {
+            miette!(
+                help = "use --header Name=value",
+                "invalid header `{}`",
+                header
+            )
+        })?;
+        let name: HeaderName = name
+            .trim()
+            .parse()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("invalid header name `{}`", name))?;
+        let value: HeaderValue = value
+            .trim()
+            .parse()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("invalid value for header `{}`", name))?;
+        headers.insert(name, value);
+    }
+
+    if let Some(authorization) = authorization(args)? {
+        let mut value: HeaderValue = authorization
+            .parse()
+            .into_diagnostic()
+            .wrap_err("invalid credentials")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+
+    if let Some(path) = &args.ca_cert {
+        let pem = read_file(path)?;
+        let cert = Certificate::from_pem(&pem)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("invalid CA certificate {}", path.display()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if let (Some(cert_path), Some(key_path)) = (&args.client_cert, &args.client_key) {
+        let identity = Identity::from_pkcs8_pem(&read_file(cert_path)?, &read_file(key_path)?)
+            .into_diagnostic()
+            .wrap_err("invalid client certificate or key")?;
+        builder = builder.identity(identity);
+    }
+
+    if args.insecure_skip_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    let client = builder.build().into_diagnostic()?;
+    Client::from(client, addr).into_diagnostic()
+}
+
+fn authorization(args: &ClientArgs) -> Result<Option<String>> {
+    if let Some(token) = &args.bearer_token {
+        return Ok(Some(format!("Bearer {}", token.trim())));
+    }
+
+    if let Some(path) = &args.bearer_token_file {
+        let token = String::from_utf8(read_file(path)?).into_diagnostic()?;
+        return Ok(Some(format!("Bearer {}", token.trim())));
+    }
+
+    let Some(basic_auth) = &args.basic_auth else {
+        return Ok(None);
+    };
+    let (user, password) = match basic_auth.split_once(':') {
+        Some((user, password)) => (user.to_string(), password.to_string()),
+        None => {
+            let password = match &args.basic_auth_password_file {
+                Some(path) => String::from_utf8(read_file(path)?)
+                    .into_diagnostic()?
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string(),
+                None => env::var(PASSWORD_ENV).map_err(|_| {
+                    miette!(
+                        help = format!(
+                            "use --basic-auth user:pass, --basic-auth-password-file or set ${}",
+                            PASSWORD_ENV
+                        ),
+                        "no password given for basic auth user `{}`",
+                        basic_auth
+                    )
+                })?,
+            };
+            (basic_auth.clone(), password)
+        }
+    };
+
+    let credentials = BASE64_STANDARD.encode(format!("{}:{}", user, password));
+    Ok(Some(format!("Basic {}", credentials)))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to read {}", path.display()))
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use miette::{IntoDiagnostic, LabeledSpan, NamedSource, Result, WrapErr, miette};
+use serde::Deserialize;
+
+use crate::{
+    Backend, client::ClientArgs, style::ChartStyle, units::Unit, variables::VariableConfig,
+};
+
+/// Contents of `$XDG_CONFIG_HOME/promegraph/config.toml`.
+///
+/// ```toml
+/// default_datasource = "prod"
+///
+/// [datasources.prod]
+/// address = "https://prometheus.example.com"
+/// step = "30s"
+/// bearer_token_file = "/run/secrets/prometheus-token"
+///
+/// [queries.http-errors]
+/// expr = "sum(rate(http_requests_total{code=~'5..'}[5m])) by (handler)"
+/// legend = "{{handler}}"
+/// duration = "6h"
+/// backend = "ratatui"
+///
+/// [variables.handler]
+/// query = "label_values(http_requests_total, handler)"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_datasource: Option<String>,
+    pub datasources: BTreeMap<String, Datasource>,
+    pub queries: BTreeMap<String, SavedQuery>,
+    pub variables: BTreeMap<String, VariableConfig>,
+}
+
+/// Keys of a datasource table, its own and the client options.
+const DATASOURCE_FIELDS: &[&str] = &[
+    "address",
+    "step",
+    "bearer_token",
+    "bearer_token_file",
+    "basic_auth",
+    "basic_auth_password_file",
+    "headers",
+    "ca_cert",
+    "client_cert",
+    "client_key",
+    "insecure_skip_verify",
+];
+
+/// A named Prometheus endpoint, selected with `--datasource`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(try_from = "RawDatasource")]
+pub struct Datasource {
+    pub address: Option<String>,
+    pub step: Option<String>,
+    pub client: ClientArgs,
+}
+
+/// A datasource as written, keeping the keys nothing reads: `deny_unknown_fields`
+/// does not work with `flatten`, and a misspelled address must not send the
+/// credentials to the default server.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RawDatasource {
+    address: Option<String>,
+    step: Option<String>,
+    #[serde(flatten)]
+    client: ClientArgs,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl TryFrom<RawDatasource> for Datasource {
+    type Error = String;
+
+    fn try_from(raw: RawDatasource) -> Result<Self, String> {
+        if let Some(key) = raw.unknown.keys().next() {
+            let expected: Vec<String> = DATASOURCE_FIELDS
+                .iter()
+                .map(|f| format!("`{}`", f))
+                .collect();
+            return Err(format!(
+                "unknown field `{}`, expected one of {}",
+                key,
+                expected.join(", ")
+            ));
+        }
+        Ok(Self {
+            address: raw.address,
+            step: raw.step,
+            client: raw.client,
+        })
+    }
+}
+
+/// A named expression, used on the command line as `@name`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQuery {
+    pub expr: String,
+    pub legend: Option<String>,
+    pub duration: Option<String>,
+    pub step: Option<String>,
+    pub backend: Option<Backend>,
+    pub datasource: Option<String>,
+    pub unit: Option<Unit>,
+    pub style: Option<ChartStyle>,
+}
+
+impl Config {
+    /// Loads `path`, or the default location when none is given. Only an explicitly
+    /// given file has to exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+
+        toml::from_str(&text).map_err(|err| {
+            let labels: Vec<LabeledSpan> = err
+                .span()
+                .map(|span| LabeledSpan::at(span, err.message().to_string()))
+                .into_iter()
+                .collect();
+            miette!(labels = labels, "invalid config file {}", path.display())
+                .with_source_code(NamedSource::new(path.display().to_string(), text))
+        })
+    }
+
+    pub fn datasource(&self, name: &str) -> Result<&Datasource> {
+        self.datasources.get(name).ok_or_else(|| {
+            miette!(
+                help = available("datasources", self.datasources.keys()),
+                "unknown datasource `{}`",
+                name
+            )
+        })
+    }
+
+    pub fn query(&self, name: &str) -> Result<&SavedQuery> {
+        self.queries.get(name).ok_or_else(|| {
+            miette!(
+                help = available("saved queries", self.queries.keys()),
+                "unknown saved query `@{}`",
+                name
+            )
+        })
+    }
+}
+
+fn available<'a>(what: &str, names: impl Iterator<Item = &'a String>) -> String {
+    let names: Vec<&str> = names.map(String::as_str).collect();
+    if names.is_empty() {
+        format!("no {} are defined in the config file", what)
+    } else {
+        format!("available {}: {}", what, names.join(", "))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("promegraph").join("config.toml"))
+}
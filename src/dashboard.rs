@@ -0,0 +1,592 @@
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use miette::{IntoDiagnostic, LabeledSpan, NamedSource, Result, WrapErr, miette};
+use prometheus_http_query::Client;
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Cell, Paragraph, Row, Table, Wrap},
+};
+use serde::Deserialize;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    backend_ratatui::{self, ChartOptions, Legend, Mode, PanelData},
+    promql::{InstantValue, Query},
+    style::ChartStyle,
+    terminal,
+    timerange::{self, Step, TimeRange},
+    units::Unit,
+    variables::{self, Variable},
+};
+
+/// Width of the dashboard grid, as in Grafana.
+const GRID_COLUMNS: u16 = 24;
+
+/// Height of a panel without a grid position, in grid rows.
+const DEFAULT_PANEL_HEIGHT: u16 = 8;
+
+/// A dashboard definition, loaded from TOML or YAML.
+///
+/// ```toml
+/// title = "API"
+/// duration = "6h"
+/// refresh = "30s"
+///
+/// [[panels]]
+/// title = "Requests"
+/// expr = "sum(rate(http_requests_total[5m])) by (code)"
+/// legend = "{{code}}"
+/// grid = { x = 0, y = 0, w = 12, h = 8 }
+///
+/// [[panels]]
+/// title = "Targets up"
+/// type = "gauge"
+/// expr = "avg(up)"
+/// grid = { x = 12, y = 0, w = 12, h = 8 }
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Dashboard {
+    pub title: Option<String>,
+    pub datasource: Option<String>,
+    /// Range of every panel [default: 1h]
+    pub duration: Option<String>,
+    /// Refresh interval of panels without their own, like 30s, bare numbers are
+    /// seconds as for `--refresh`
+    pub refresh: Option<String>,
+    pub panels: Vec<Panel>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Panel {
+    pub title: Option<String>,
+    #[serde(rename = "type", default)]
+    pub kind: PanelKind,
+    pub expr: Option<String>,
+    pub legend: Option<String>,
+    /// Further expressions drawn in the same panel
+    #[serde(default)]
+    pub targets: Vec<Target>,
+    pub unit: Option<String>,
+    /// How timeseries panels draw their series [default: line]
+    #[serde(default)]
+    pub style: ChartStyle,
+    pub refresh: Option<String>,
+    /// Upper bound of gauge panels [default: 1]
+    pub max: Option<f64>,
+    pub grid: Option<GridPos>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub expr: String,
+    pub legend: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelKind {
+    #[default]
+    Timeseries,
+    Bar,
+    Gauge,
+    Stat,
+    Table,
+    Heatmap,
+}
+
+/// Position on a grid `GRID_COLUMNS` wide, `y` and `h` in rows.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct GridPos {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+
+impl Dashboard {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let source = || NamedSource::new(path.display().to_string(), text.clone());
+
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let dashboard: Dashboard = match extension {
+            Some("yaml" | "yml") => serde_norway::from_str(&text).map_err(|err| {
+                let labels: Vec<LabeledSpan> = err
+                    .location()
+                    .map(|loc| LabeledSpan::at_offset(loc.index(), err.to_string()))
+                    .into_iter()
+                    .collect();
+                miette!(labels = labels, "invalid dashboard {}", path.display())
+                    .with_source_code(source())
+            })?,
+            _ => toml::from_str(&text).map_err(|err| {
+                let labels: Vec<LabeledSpan> = err
+                    .span()
+                    .map(|span| LabeledSpan::at(span, err.message().to_string()))
+                    .into_iter()
+                    .collect();
+                miette!(labels = labels, "invalid dashboard {}", path.display())
+                    .with_source_code(source())
+            })?,
+        };
+
+        for (i, panel) in dashboard.panels.iter().enumerate() {
+            if panel.queries().is_empty() {
+                return Err(miette!(
+                    help = "set `expr` or add `targets`",
+                    "panel {} ({}) has no expression",
+                    i + 1,
+                    panel.title.as_deref().unwrap_or("untitled")
+                ));
+            }
+        }
+
+        Ok(dashboard)
+    }
+
+    /// Every query of every panel.
+    pub fn queries(&self) -> Vec<Query> {
+        self.panels.iter().flat_map(Panel::queries).collect()
+    }
+
+    /// Fills the variables' values into the panels' expressions and legends.
+    pub fn apply(&mut self, variables: &[Variable]) {
+        for panel in &mut self.panels {
+            let mut queries = variables::apply(&panel.queries(), variables).into_iter();
+            if panel.expr.is_some() {
+                let first = queries.next().expect("one query per expression");
+                panel.expr = Some(first.expr);
+                panel.legend = first.legend;
+            }
+            for (target, query) in panel.targets.iter_mut().zip(queries) {
+                target.expr = query.expr;
+                target.legend = query.legend;
+            }
+        }
+    }
+}
+
+impl Panel {
+    pub fn queries(&self) -> Vec<Query> {
+        let first = self.expr.as_ref().map(|expr| Query {
+            expr: expr.clone(),
+            legend: self.legend.clone(),
+        });
+        first
+            .into_iter()
+            .chain(self.targets.iter().map(|t| Query {
+                expr: t.expr.clone(),
+                legend: t.legend.clone(),
+            }))
+            .collect()
+    }
+
+    /// `defaults` with the mode the panel's type queries, its unit and its style.
+    fn options(&self, defaults: &ChartOptions) -> ChartOptions {
+        let mode = match self.kind {
+            PanelKind::Timeseries => Mode::Range,
+            PanelKind::Heatmap => Mode::Heatmap,
+            _ => Mode::Instant,
+        };
+        ChartOptions {
+            mode,
+            unit: self.unit(),
+            style: self.style,
+            ..defaults.clone()
+        }
+    }
+
+    /// The panel's unit when it names a known one, otherwise the inferred one.
+    fn unit(&self) -> Unit {
+        self.unit
+            .as_deref()
+            .and_then(Unit::from_name)
+            .unwrap_or_else(|| Unit::infer(&self.queries()))
+    }
+
+    fn title(&self) -> String {
+        let title = match &self.title {
+            Some(title) => title.clone(),
+            None => self
+                .queries()
+                .first()
+                .map(|q| q.expr.clone())
+                .unwrap_or_default(),
+        };
+        match &self.unit {
+            Some(unit) => format!(" {} ({}) ", title, unit),
+            None => format!(" {} ", title),
+        }
+    }
+}
+
+type PanelResult = std::result::Result<PanelData, String>;
+
+pub struct DashboardView {
+    client: Client,
+    dashboard: Dashboard,
+    range: TimeRange,
+    refresh: u64,
+    /// What panels share, each choosing its own mode, unit and style
+    options: ChartOptions,
+}
+
+impl DashboardView {
+    pub fn new(
+        client: Client,
+        dashboard: Dashboard,
+        range: TimeRange,
+        refresh: u64,
+        options: ChartOptions,
+    ) -> Self {
+        Self {
+            client,
+            dashboard,
+            range,
+            refresh,
+            options,
+        }
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let tasks: Vec<JoinHandle<()>> = self
+            .dashboard
+            .panels
+            .iter()
+            .enumerate()
+            .map(|(i, panel)| self.spawn_panel(i, panel, tx.clone()))
+            .collect::<Result<_>>()?;
+
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal, rx).await;
+        ratatui::restore();
+
+        for task in tasks {
+            task.abort();
+        }
+        result
+    }
+
+    /// Refreshes one panel on its own interval, sending each result back to the UI.
+    fn spawn_panel(
+        &self,
+        index: usize,
+        panel: &Panel,
+        tx: mpsc::UnboundedSender<(usize, PanelResult)>,
+    ) -> Result<JoinHandle<()>> {
+        let refresh = match panel
+            .refresh
+            .as_deref()
+            .or(self.dashboard.refresh.as_deref())
+        {
+            Some(refresh) => timerange::parse_interval("refresh", refresh)?,
+            None => self.refresh,
+        };
+
+        let client = self.client.clone();
+        let panel_queries = panel.queries();
+        let width = panel.grid.map(|g| g.w).unwrap_or(GRID_COLUMNS);
+        let range = self.range.clone();
+        let options = panel.options(&self.options);
+
+        Ok(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(refresh.max(1)));
+            loop {
+                interval.tick().await;
+                let (columns, _) = terminal::size();
+                let columns = columns * width.min(GRID_COLUMNS) / GRID_COLUMNS;
+                let points = backend_ratatui::plot_width(columns);
+                // Resolved here, heatmaps at their coarser resolution, so the macros
+                // expand to the step queried
+                let step = options.step_seconds(&range, points);
+                let queries: Vec<Query> = panel_queries
+                    .iter()
+                    .map(|q| Query {
+                        expr: expand_macros(&q.expr, step, &range),
+                        legend: q.legend.clone(),
+                    })
+                    .collect();
+                let options = ChartOptions {
+                    step: Step::Fixed(step),
+                    ..options.clone()
+                };
+                let result =
+                    backend_ratatui::fetch_panel(&client, &queries, &range, &options, points)
+                        .await
+                        .map_err(|err| format!("{:?}", err));
+                if tx.send((index, result)).is_err() {
+                    break;
+                }
+            }
+        }))
+    }
+
+    async fn event_loop(
+        &self,
+        terminal: &mut DefaultTerminal,
+        mut rx: mpsc::UnboundedReceiver<(usize, PanelResult)>,
+    ) -> Result<()> {
+        let mut data: Vec<Option<PanelResult>> =
+            self.dashboard.panels.iter().map(|_| None).collect();
+        let mut focused = 0;
+        let mut maximized = false;
+        let panel_count = self.dashboard.panels.len();
+
+        loop {
+            while let Ok((index, result)) = rx.try_recv() {
+                data[index] = Some(result);
+            }
+
+            terminal
+                .draw(|frame| self.draw(frame, &data, focused, maximized))
+                .into_diagnostic()?;
+
+            if event::poll(Duration::from_millis(250)).into_diagnostic()?
+                && let Event::Key(key) = event::read().into_diagnostic()?
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') if panel_count > 0 => {
+                        focused = (focused + 1) % panel_count;
+                    }
+                    KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') if panel_count > 0 => {
+                        focused = (focused + panel_count - 1) % panel_count;
+                    }
+                    KeyCode::Enter | KeyCode::Char('f') => maximized = !maximized,
+                    KeyCode::Esc => maximized = false,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        frame: &mut Frame,
+        data: &[Option<PanelResult>],
+        focused: usize,
+        maximized: bool,
+    ) {
+        let [header, body] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+
+        let title = self.dashboard.title.as_deref().unwrap_or("Dashboard");
+        frame.render_widget(
+            Line::from(vec![
+                Span::styled(title, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" | tab/←/→ focus, enter maximize, esc restore, q quit"),
+            ]),
+            header,
+        );
+
+        let panels = &self.dashboard.panels;
+        if maximized && let Some(panel) = panels.get(focused) {
+            let options = panel.options(&self.options);
+            render_panel(frame, body, panel, &options, &data[focused], true);
+            return;
+        }
+
+        for (i, rect) in layout(panels, body).into_iter().enumerate() {
+            let options = panels[i].options(&self.options);
+            render_panel(frame, rect, &panels[i], &options, &data[i], i == focused);
+        }
+    }
+}
+
+fn render_panel(
+    frame: &mut Frame,
+    area: Rect,
+    panel: &Panel,
+    options: &ChartOptions,
+    data: &Option<PanelResult>,
+    focused: bool,
+) {
+    let border_style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let block = Block::bordered()
+        .title(panel.title())
+        .border_style(border_style);
+
+    match data {
+        None => frame.render_widget(Paragraph::new("Loading...").block(block), area),
+        Some(Err(err)) => frame.render_widget(
+            Paragraph::new(err.as_str())
+                .style(Style::default().fg(Color::Red))
+                .wrap(Wrap { trim: false })
+                .block(block),
+            area,
+        ),
+        Some(Ok(PanelData::Range(series))) => {
+            let mut legend = Legend::default();
+            backend_ratatui::render_chart(frame, area, block, series, &mut legend, None, options);
+        }
+        Some(Ok(PanelData::Instant(values))) => match panel.kind {
+            PanelKind::Gauge => {
+                let max = match panel.unit() {
+                    Unit::Percent => panel.max.unwrap_or(100.0),
+                    _ => panel.max.unwrap_or(1.0),
+                };
+                backend_ratatui::render_gauges(frame, area, block, values, max, panel.unit())
+            }
+            PanelKind::Stat => render_stat(frame, area, block, values, panel.unit()),
+            PanelKind::Table => render_table(frame, area, block, values, panel.unit()),
+            _ => backend_ratatui::render_bars(frame, area, block, values, panel.unit()),
+        },
+        Some(Ok(PanelData::Heatmap(heatmap))) => {
+            backend_ratatui::render_heatmap(frame, area, block, heatmap, panel.unit())
+        }
+    }
+}
+
+/// Shows each value as a bold number under its label.
+fn render_stat(frame: &mut Frame, area: Rect, block: Block, values: &[InstantValue], unit: Unit) {
+    let lines: Vec<Line> = values
+        .iter()
+        .flat_map(|v| {
+            [
+                Line::from(Span::styled(
+                    unit.format(v.value),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+                Line::from(v.label.clone()),
+            ]
+        })
+        .collect();
+
+    let paragraph = Paragraph::new(lines)
+        .centered()
+        .wrap(Wrap { trim: true })
+        .block(block);
+    frame.render_widget(paragraph, area);
+}
+
+/// Lists each series with its value, largest first.
+fn render_table(frame: &mut Frame, area: Rect, block: Block, values: &[InstantValue], unit: Unit) {
+    let rows: Vec<Row> = values
+        .iter()
+        .map(|v| {
+            Row::new(vec![
+                Cell::from(v.label.clone()),
+                Cell::from(Line::from(unit.format(v.value)).right_aligned()),
+            ])
+        })
+        .collect();
+
+    let header = Row::new(vec![
+        Cell::from("Series"),
+        Cell::from(Line::from("Value").right_aligned()),
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(14)])
+        .header(header)
+        .block(block);
+    frame.render_widget(table, area);
+}
+
+/// Expands Grafana's global variables, so panels imported from Grafana run unchanged.
+/// `$__rate_interval` assumes Prometheus' default 15s scrape interval.
+fn expand_macros(expr: &str, step: f64, range: &TimeRange) -> String {
+    if !expr.contains("$__") {
+        return expr.to_string();
+    }
+    let (start, end) = range.bounds();
+    let range = end - start;
+    let step_ms = (step * 1000.0).round() as i64;
+    let rate_interval_ms = (step_ms + 15_000).max(60_000);
+
+    // Longer names first, since they share prefixes
+    expr.replace("$__rate_interval", &format_duration(rate_interval_ms))
+        .replace("$__interval_ms", &step_ms.to_string())
+        .replace("$__interval", &format_duration(step_ms))
+        .replace("$__range_ms", &(range * 1000).to_string())
+        .replace("$__range_s", &range.to_string())
+        .replace("$__range", &format!("{}s", range))
+}
+
+/// Formats milliseconds as a PromQL duration.
+fn format_duration(ms: i64) -> String {
+    if ms % 1000 == 0 {
+        format!("{}s", ms / 1000)
+    } else {
+        format!("{}ms", ms)
+    }
+}
+
+/// Places panels on the grid: panels sharing a `y` form a row as tall as its tallest
+/// panel, and panels without a position get a full-width row of their own.
+fn layout(panels: &[Panel], area: Rect) -> Vec<Rect> {
+    let mut next_y = panels
+        .iter()
+        .filter_map(|p| p.grid.map(|g| g.y + g.h))
+        .max()
+        .unwrap_or(0);
+    let positions: Vec<GridPos> = panels
+        .iter()
+        .map(|p| {
+            p.grid.unwrap_or_else(|| {
+                let pos = GridPos {
+                    x: 0,
+                    y: next_y,
+                    w: GRID_COLUMNS,
+                    h: DEFAULT_PANEL_HEIGHT,
+                };
+                next_y += DEFAULT_PANEL_HEIGHT;
+                pos
+            })
+        })
+        .collect();
+
+    let mut rows: BTreeMap<u16, Vec<usize>> = BTreeMap::new();
+    for (i, pos) in positions.iter().enumerate() {
+        rows.entry(pos.y).or_default().push(i);
+    }
+
+    let row_heights: Vec<Constraint> = rows
+        .values()
+        .map(|row| Constraint::Fill(row.iter().map(|&i| positions[i].h).max().unwrap_or(1)))
+        .collect();
+    let row_areas = Layout::vertical(row_heights).split(area);
+
+    let mut rects = vec![Rect::default(); panels.len()];
+    for (row, row_area) in rows.values_mut().zip(row_areas.iter()) {
+        row.sort_by_key(|&i| positions[i].x);
+
+        // Gaps between panels become empty spacers
+        let mut constraints = Vec::new();
+        let mut slots = Vec::new();
+        let mut cursor = 0;
+        for &i in row.iter() {
+            let pos = positions[i];
+            if pos.x > cursor {
+                constraints.push(Constraint::Fill(pos.x - cursor));
+            }
+            slots.push((i, constraints.len()));
+            constraints.push(Constraint::Fill(pos.w.max(1)));
+            cursor = cursor.max(pos.x) + pos.w;
+        }
+        if cursor < GRID_COLUMNS {
+            constraints.push(Constraint::Fill(GRID_COLUMNS - cursor));
+        }
+
+        let cells = Layout::horizontal(constraints).split(*row_area);
+        for (i, slot) in slots {
+            rects[i] = cells[slot];
+        }
+    }
+    rects
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use clap::ValueEnum;
+use jiff::Timestamp;
+use miette::{IntoDiagnostic, Result, miette};
+use serde::Serialize;
+
+use crate::{
+    histogram::{HistogramSample, RawHistogram},
+    promql::{self, RangeSeries, Sample},
+    series::timestamp_key,
+};
+
+/// Horizontal positions the automatic step is sized for, as there is no chart.
+pub const POINTS: u32 = 1000;
+
+/// Machine-readable output of range query results.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Format {
+    /// Comma separated, a row per sample or with --wide a column per series
+    Csv,
+    /// Tab separated, laid out like csv
+    Tsv,
+    /// An array of series in the shape of the query_range API, plus their names
+    Json,
+    /// A JSON object per sample, one per line
+    Ndjson,
+    /// OpenMetrics text, for promtool tsdb create-blocks-from openmetrics
+    Openmetrics,
+}
+
+/// A series as exported, its display name next to the API's fields.
+#[derive(Serialize)]
+struct NamedSeries<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    series: &'a RangeSeries,
+}
+
+/// A sample as written to ndjson, with either a float value or a native histogram.
+#[derive(Serialize)]
+struct SampleLine<'a> {
+    name: &'a str,
+    metric: BTreeMap<&'a String, &'a String>,
+    timestamp: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<RawHistogram>,
+}
+
+/// Writes the series in `format`. `wide` lays csv and tsv out with a column per
+/// series instead of a row per sample. A reader closing the pipe early, like
+/// `head` does, is not an error.
+pub fn write(
+    out: &mut impl Write,
+    format: Format,
+    wide: bool,
+    data: &[(String, RangeSeries)],
+) -> Result<()> {
+    let result = match format {
+        Format::Csv if wide => write_wide(out, ',', &flatten_histograms(data)),
+        Format::Csv => write_long(out, ',', &flatten_histograms(data)),
+        Format::Tsv if wide => write_wide(out, '\t', &flatten_histograms(data)),
+        Format::Tsv => write_long(out, '\t', &flatten_histograms(data)),
+        Format::Json => write_json(out, data),
+        Format::Ndjson => write_ndjson(out, data),
+        Format::Openmetrics => {
+            let data = flatten_histograms(data);
+            write_openmetrics(out, families(&data)?)
+        }
+    };
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.into_diagnostic(),
+    }
+}
+
+/// Replaces native histograms by their `_count` and `_sum` series, since table and
+/// exposition formats have no place for buckets.
+fn flatten_histograms(data: &[(String, RangeSeries)]) -> Vec<(String, RangeSeries)> {
+    let mut result = Vec::new();
+    for (label, series) in data {
+        if !series.samples().is_empty() || series.histograms().is_empty() {
+            result.push((
+                label.clone(),
+                series.with_samples(series.samples().to_vec()),
+            ));
+        }
+        if series.histograms().is_empty() {
+            continue;
+        }
+        let flat = |suffix: &str, value: fn(&HistogramSample) -> f64| {
+            let samples = series
+                .histograms()
+                .iter()
+                .map(|h| Sample::new(h.timestamp, value(h)))
+                .collect();
+            let mut flat = series.with_samples(samples);
+            if let Some(name) = series.metric().get("__name__") {
+                flat.set_label("__name__", format!("{}_{}", name, suffix));
+            }
+            (format!("{} {}", label, suffix), flat)
+        };
+        result.push(flat("count", |h| h.count));
+        result.push(flat("sum", |h| h.sum));
+    }
+    result
+}
+
+fn write_json(out: &mut impl Write, data: &[(String, RangeSeries)]) -> io::Result<()> {
+    let series: Vec<NamedSeries> = data
+        .iter()
+        .map(|(name, series)| NamedSeries { name, series })
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &series)?;
+    writeln!(out)
+}
+
+fn write_ndjson(out: &mut impl Write, data: &[(String, RangeSeries)]) -> io::Result<()> {
+    for (name, series) in data {
+        let line = |timestamp| SampleLine {
+            name,
+            metric: series.metric().iter().collect(),
+            timestamp,
+            value: None,
+            histogram: None,
+        };
+        let values = series.samples().iter().map(|s| SampleLine {
+            value: Some(promql::format_value(s.value())),
+            ..line(s.timestamp())
+        });
+        let histograms = series.histograms().iter().map(|h| SampleLine {
+            histogram: Some(h.to_raw()),
+            ..line(h.timestamp)
+        });
+        for sample in values.chain(histograms) {
+            serde_json::to_writer(&mut *out, &sample)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// A row per sample: its time, the series name, a column per label name found in
+/// any series, then the value.
+fn write_long(
+    out: &mut impl Write,
+    separator: char,
+    data: &[(String, RangeSeries)],
+) -> io::Result<()> {
+    let labels: BTreeSet<&String> = data.iter().flat_map(|(_, v)| v.metric().keys()).collect();
+
+    let header = ["timestamp", "name"]
+        .into_iter()
+        .chain(labels.iter().map(|l| l.as_str()))
+        .chain(["value"]);
+    write_row(out, separator, header)?;
+
+    for (name, series) in data {
+        let label_values: Vec<&str> = labels
+            .iter()
+            .map(|l| series.metric().get(*l).map_or("", String::as_str))
+            .collect();
+        for s in series.samples() {
+            let time = format_time(s.timestamp());
+            let value = promql::format_value(s.value());
+            let row = [time.as_str(), name.as_str()]
+                .into_iter()
+                .chain(label_values.iter().copied())
+                .chain([value.as_str()]);
+            write_row(out, separator, row)?;
+        }
+    }
+    Ok(())
+}
+
+/// A row per timestamp of any series and a column per series, empty where a
+/// series has no sample.
+fn write_wide(
+    out: &mut impl Write,
+    separator: char,
+    data: &[(String, RangeSeries)],
+) -> io::Result<()> {
+    let mut rows: BTreeMap<i64, (f64, Vec<String>)> = BTreeMap::new();
+    for (column, (_, series)) in data.iter().enumerate() {
+        for s in series.samples() {
+            let (_, values) = rows
+                .entry(timestamp_key(s.timestamp()))
+                .or_insert_with(|| (s.timestamp(), vec![String::new(); data.len()]));
+            values[column] = promql::format_value(s.value());
+        }
+    }
+
+    let header = ["timestamp"]
+        .into_iter()
+        .chain(data.iter().map(|(name, _)| name.as_str()));
+    write_row(out, separator, header)?;
+    for (ts, values) in rows.values() {
+        let time = format_time(*ts);
+        let row = [time.as_str()]
+            .into_iter()
+            .chain(values.iter().map(String::as_str));
+        write_row(out, separator, row)?;
+    }
+    Ok(())
+}
+
+/// Writes one line, quoting fields that hold the separator, quotes or line breaks.
+fn write_row<'a>(
+    out: &mut impl Write,
+    separator: char,
+    fields: impl IntoIterator<Item = &'a str>,
+) -> io::Result<()> {
+    let line: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([separator, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    writeln!(out, "{}", line.join(&separator.to_string()))
+}
+
+/// RFC 3339 in UTC, which spreadsheets read as dates.
+fn format_time(ts: f64) -> String {
+    match Timestamp::from_millisecond((ts * 1000.0).round() as i64) {
+        Ok(t) => t.to_string(),
+        Err(_) => ts.to_string(),
+    }
+}
+
+/// Series grouped by metric name, each keyed by its labels in exposition syntax and
+/// holding its samples by timestamp.
+type Families<'a> = BTreeMap<&'a str, BTreeMap<String, BTreeMap<i64, Sample>>>;
+
+/// Groups the series into metric families, which OpenMetrics requires to be
+/// contiguous. Every series needs a name. Queries returning the same series are
+/// merged into one, in time order, the first query's sample winning where both
+/// have one.
+fn families(data: &[(String, RangeSeries)]) -> Result<Families<'_>> {
+    let mut families = Families::new();
+    for (label, series) in data {
+        let Some(name) = series.metric().get("__name__") else {
+            return Err(miette!(
+                help = "name it with label_replace(<expr>, \"__name__\", \"<name>\", \"\", \"\")",
+                "series `{}` has no metric name, which OpenMetrics requires",
+                label
+            ));
+        };
+        let mut labels: Vec<(&String, &String)> = series
+            .metric()
+            .iter()
+            .filter(|(key, _)| *key != "__name__")
+            .collect();
+        labels.sort();
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let samples = families.entry(name).or_default().entry(labels).or_default();
+        for s in series.samples() {
+            samples.entry(timestamp_key(s.timestamp())).or_insert(*s);
+        }
+    }
+    Ok(families)
+}
+
+/// Writes each family with its samples timestamped in seconds. The type is left
+/// unknown, range query results have lost it.
+fn write_openmetrics(out: &mut impl Write, families: Families) -> io::Result<()> {
+    for (name, series) in families {
+        writeln!(out, "# TYPE {} unknown", name)?;
+        for (labels, samples) in series {
+            let labels = if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels)
+            };
+            for s in samples.values() {
+                writeln!(
+                    out,
+                    "{}{} {} {}",
+                    name,
+                    labels,
+                    promql::format_value(s.value()),
+                    s.timestamp()
+                )?;
+            }
+        }
+    }
+    writeln!(out, "# EOF")
+}
+
+/// Escapes a label value for the exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(name: &str, metric: &str, values: &str) -> (String, RangeSeries) {
+        let body = format!(
+            r#"{{"status":"success","data":{{"resultType":"matrix",
+                "result":[{{"metric":{},"values":{}}}]}}}}"#,
+            metric, values
+        );
+        let series = promql::parse_range_response(body.as_bytes()).unwrap();
+        (name.to_string(), series.into_iter().next().unwrap())
+    }
+
+    fn export(format: Format, wide: bool, data: &[(String, RangeSeries)]) -> String {
+        let mut out = Vec::new();
+        write(&mut out, format, wide, data).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let data = [series(
+            "a, \"b\"",
+            r#"{"__name__":"up","path":"/x,y"}"#,
+            r#"[[0,"1"]]"#,
+        )];
+        assert_eq!(
+            export(Format::Csv, false, &data),
+            "timestamp,name,__name__,path,value\n\
+             1970-01-01T00:00:00Z,\"a, \"\"b\"\"\",up,\"/x,y\",1\n"
+        );
+    }
+
+    #[test]
+    fn tsv_quotes_only_tabs() {
+        let data = [series("a,b\tc", r#"{}"#, r#"[[0,"1"]]"#)];
+        assert_eq!(
+            export(Format::Tsv, false, &data),
+            "timestamp\tname\tvalue\n1970-01-01T00:00:00Z\t\"a,b\tc\"\t1\n"
+        );
+    }
+
+    #[test]
+    fn wide_aligns_samples_by_timestamp() {
+        let data = [
+            series("a", r#"{}"#, r#"[[0,"1"],[15,"2"]]"#),
+            series("b", r#"{}"#, r#"[[15,"3"],[30,"NaN"]]"#),
+        ];
+        assert_eq!(
+            export(Format::Csv, true, &data),
+            "timestamp,a,b\n\
+             1970-01-01T00:00:00Z,1,\n\
+             1970-01-01T00:00:15Z,2,3\n\
+             1970-01-01T00:00:30Z,,NaN\n"
+        );
+    }
+
+    #[test]
+    fn openmetrics_families() {
+        let data = [
+            series("b", r#"{"__name__":"up","job":"b"}"#, r#"[[0,"1"]]"#),
+            series("x", r#"{"__name__":"other"}"#, r#"[[0,"2"]]"#),
+            series("a", r#"{"__name__":"up","job":"a\"q"}"#, r#"[[0,"0"]]"#),
+        ];
+        assert_eq!(
+            export(Format::Openmetrics, false, &data),
+            "# TYPE other unknown\n\
+             other 2 0\n\
+             # TYPE up unknown\n\
+             up{job=\"a\\\"q\"} 0 0\n\
+             up{job=\"b\"} 1 0\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn openmetrics_merges_identical_series() {
+        let data = [
+            series("first", r#"{"__name__":"up"}"#, r#"[[15,"1"],[30,"1"]]"#),
+            series("second", r#"{"__name__":"up"}"#, r#"[[0,"2"],[15,"2"]]"#),
+        ];
+        assert_eq!(
+            export(Format::Openmetrics, false, &data),
+            "# TYPE up unknown\nup 2 0\nup 1 15\nup 1 30\n# EOF\n"
+        );
+    }
+
+    #[test]
+    fn openmetrics_requires_names() {
+        let data = [series("a", r#"{"job":"a"}"#, r#"[[0,"1"]]"#)];
+        let mut out = Vec::new();
+        assert!(write(&mut out, Format::Openmetrics, false, &data).is_err());
+    }
+}
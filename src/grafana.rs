@@ -0,0 +1,444 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use miette::{IntoDiagnostic, LabeledSpan, NamedSource, Report, Result, Severity, WrapErr, miette};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    dashboard::{Dashboard, GridPos, Panel, PanelKind, Target},
+    style::ChartStyle,
+    timerange,
+    variables::{self, substitute},
+};
+
+/// The parts of Grafana's dashboard JSON model promegraph understands. Everything
+/// else, like thresholds, overrides and links, is ignored.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GrafanaDashboard {
+    title: Option<String>,
+    time: Option<GrafanaTime>,
+    /// A duration like "30s", or `false` when auto refresh is off
+    refresh: Option<Value>,
+    #[serde(default)]
+    panels: Vec<GrafanaPanel>,
+    #[serde(default)]
+    templating: Templating,
+}
+
+#[derive(Deserialize, Debug)]
+struct GrafanaTime {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Templating {
+    #[serde(default)]
+    list: Vec<Variable>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Variable {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    current: Option<Current>,
+    all_value: Option<String>,
+    /// The value of constant variables
+    query: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Current {
+    value: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GrafanaPanel {
+    title: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    datasource: Option<Value>,
+    #[serde(default)]
+    targets: Vec<GrafanaTarget>,
+    grid_pos: Option<GrafanaGridPos>,
+    field_config: Option<FieldConfig>,
+    /// Panels inside a collapsed row
+    #[serde(default)]
+    panels: Vec<GrafanaPanel>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GrafanaTarget {
+    expr: Option<String>,
+    legend_format: Option<String>,
+    datasource: Option<Value>,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct GrafanaGridPos {
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+}
+
+#[derive(Deserialize, Debug)]
+struct FieldConfig {
+    defaults: Option<FieldDefaults>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FieldDefaults {
+    unit: Option<String>,
+    max: Option<f64>,
+    custom: Option<FieldCustom>,
+}
+
+/// Graph styling of timeseries panels.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FieldCustom {
+    draw_style: Option<String>,
+    line_interpolation: Option<String>,
+    fill_opacity: Option<f64>,
+    stacking: Option<Stacking>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Stacking {
+    mode: Option<String>,
+}
+
+impl FieldCustom {
+    fn style(&self) -> ChartStyle {
+        let stacked = self
+            .stacking
+            .as_ref()
+            .and_then(|s| s.mode.as_deref())
+            .is_some_and(|mode| mode != "none");
+        match self.draw_style.as_deref() {
+            Some("bars") => ChartStyle::Bars,
+            Some("points") => ChartStyle::Points,
+            _ if stacked => ChartStyle::Stacked,
+            _ if self.fill_opacity.is_some_and(|opacity| opacity > 0.0) => ChartStyle::Area,
+            _ if self
+                .line_interpolation
+                .as_deref()
+                .is_some_and(|i| i.starts_with("step")) =>
+            {
+                ChartStyle::Steps
+            }
+            _ => ChartStyle::Line,
+        }
+    }
+}
+
+/// Converts a Grafana dashboard export into a promegraph dashboard. Panels that
+/// cannot be shown are skipped and described in the returned warnings.
+/// Template variables take their current value from the dashboard unless given in
+/// `overrides`.
+pub fn import(
+    path: &Path,
+    overrides: &BTreeMap<String, String>,
+) -> Result<(Dashboard, Vec<Report>)> {
+    let text = fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+
+    let mut json: Value = serde_json::from_str(&text).map_err(|err| {
+        let offset = line_offset(&text, err.line(), err.column());
+        miette!(
+            labels = vec![LabeledSpan::at_offset(offset, err.to_string())],
+            "invalid Grafana dashboard {}",
+            path.display()
+        )
+        .with_source_code(NamedSource::new(path.display().to_string(), text.clone()))
+    })?;
+
+    // Dashboards fetched from the HTTP API are wrapped together with their metadata
+    if let Some(dashboard) = json.get_mut("dashboard") {
+        json = dashboard.take();
+    }
+    let grafana: GrafanaDashboard = serde_json::from_value(json)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("invalid Grafana dashboard {}", path.display()))?;
+
+    let mut warnings = Vec::new();
+    let mut variables = variables(&grafana.templating);
+    variables.extend(overrides.clone());
+
+    let mut panels = Vec::new();
+    for panel in flatten(grafana.panels) {
+        match convert_panel(&panel, &variables) {
+            Ok(Some(panel)) => panels.push(panel),
+            Ok(None) => {}
+            Err(reason) => warnings.push(miette!(
+                severity = Severity::Warning,
+                "skipped panel `{}`: {}",
+                panel.title.as_deref().unwrap_or("untitled"),
+                reason
+            )),
+        }
+    }
+
+    if panels.is_empty() {
+        // Why each panel was skipped is the only clue to what went wrong
+        for warning in warnings {
+            eprintln!("{:?}", warning);
+        }
+        return Err(miette!(
+            help = "only timeseries, graph, stat, gauge, bargauge, table and heatmap panels \
+                with Prometheus targets are imported",
+            "no panels could be imported from {}",
+            path.display()
+        ));
+    }
+
+    // Left in the queries, they would only fail later as PromQL syntax errors
+    let unresolved: BTreeSet<&str> = panels
+        .iter()
+        .flat_map(|panel| &panel.targets)
+        .flat_map(|target| {
+            let legend = target.legend.as_deref().unwrap_or_default();
+            variables::names(&target.expr)
+                .into_iter()
+                .chain(variables::names(legend))
+        })
+        .filter(|name| !name.starts_with("__") && !variables.contains_key(*name))
+        .collect();
+    for name in unresolved {
+        warnings.push(miette!(
+            severity = Severity::Warning,
+            help = format!("set one with --var {}=<value> or in the config file", name),
+            "variable `${}` has no value in the dashboard",
+            name
+        ));
+    }
+
+    let duration = match &grafana.time {
+        Some(time) if time.to == "now" => duration(&time.from, &mut warnings),
+        Some(time) => {
+            warnings.push(miette!(
+                severity = Severity::Warning,
+                help = "use --start and --end",
+                "ignored the dashboard's time range {} to {}",
+                time.from,
+                time.to
+            ));
+            None
+        }
+        None => None,
+    };
+    let refresh = grafana
+        .refresh
+        .as_ref()
+        .and_then(Value::as_str)
+        .filter(|refresh| !refresh.is_empty())
+        .map(str::to_string);
+
+    let dashboard = Dashboard {
+        title: grafana.title,
+        datasource: None,
+        duration,
+        refresh,
+        panels,
+    };
+    Ok((dashboard, warnings))
+}
+
+/// The duration of a range from `from` to now, like `6h` for `now-6h`. Ranges
+/// rounded to a whole unit, like `now-1d/d`, lose their rounding. Anything else
+/// is left to the default duration, with a warning.
+fn duration(from: &str, warnings: &mut Vec<Report>) -> Option<String> {
+    let offset = from.strip_prefix("now-").unwrap_or_default();
+    let (duration, rounding) = match offset.split_once('/') {
+        Some((duration, _)) => (duration, true),
+        None => (offset, false),
+    };
+    if timerange::parse_duration("--duration", duration).is_err() {
+        warnings.push(miette!(
+            severity = Severity::Warning,
+            help = "use --duration or --start",
+            "ignored the dashboard's time range from {}, showing the default one",
+            from
+        ));
+        return None;
+    }
+    if rounding {
+        warnings.push(miette!(
+            severity = Severity::Warning,
+            "showing the last {} of the dashboard's time range {}, without rounding it",
+            duration,
+            from
+        ));
+    }
+    Some(duration.to_string())
+}
+
+/// Lifts the panels of collapsed rows to the top level, dropping the rows themselves.
+fn flatten(panels: Vec<GrafanaPanel>) -> Vec<GrafanaPanel> {
+    let mut result = Vec::new();
+    for mut panel in panels {
+        let children = std::mem::take(&mut panel.panels);
+        if panel.kind != "row" {
+            result.push(panel);
+        }
+        result.extend(children);
+    }
+    result
+}
+
+/// Returns `Ok(None)` for panels without queries, like text panels, which are
+/// expected in dashboards and not worth a warning.
+fn convert_panel(
+    panel: &GrafanaPanel,
+    variables: &BTreeMap<String, String>,
+) -> std::result::Result<Option<Panel>, String> {
+    let kind = match panel.kind.as_str() {
+        "timeseries" | "graph" => PanelKind::Timeseries,
+        "stat" | "singlestat" => PanelKind::Stat,
+        "gauge" => PanelKind::Gauge,
+        "bargauge" => PanelKind::Bar,
+        "table" => PanelKind::Table,
+        "heatmap" => PanelKind::Heatmap,
+        "text" | "news" | "dashlist" | "alertlist" => return Ok(None),
+        other => return Err(format!("unsupported panel type `{}`", other)),
+    };
+
+    if let Some(kind) = datasource_type(panel.datasource.as_ref())
+        && kind != "prometheus"
+    {
+        return Err(format!("unsupported datasource type `{}`", kind));
+    }
+
+    let targets: Vec<Target> = panel
+        .targets
+        .iter()
+        .filter(|t| !t.hide)
+        .filter(|t| datasource_type(t.datasource.as_ref()).is_none_or(|kind| kind == "prometheus"))
+        .filter_map(|t| {
+            let expr = t.expr.as_deref().filter(|expr| !expr.trim().is_empty())?;
+            let legend = t
+                .legend_format
+                .as_deref()
+                .filter(|legend| !legend.is_empty() && *legend != "__auto")
+                .map(|legend| substitute(legend, variables));
+            Some(Target {
+                expr: substitute(expr, variables),
+                legend,
+            })
+        })
+        .collect();
+    if targets.is_empty() {
+        return Err("no Prometheus queries".to_string());
+    }
+
+    let defaults = panel
+        .field_config
+        .as_ref()
+        .and_then(|f| f.defaults.as_ref());
+    Ok(Some(Panel {
+        title: panel.title.clone().filter(|title| !title.is_empty()),
+        kind,
+        expr: None,
+        legend: None,
+        targets,
+        unit: defaults
+            .and_then(|d| d.unit.clone())
+            .filter(|unit| unit != "none"),
+        style: defaults
+            .and_then(|d| d.custom.as_ref())
+            .map(FieldCustom::style)
+            .unwrap_or_default(),
+        refresh: None,
+        max: defaults.and_then(|d| d.max),
+        grid: panel.grid_pos.as_ref().map(|g| GridPos {
+            x: g.x,
+            y: g.y,
+            w: g.w,
+            h: g.h,
+        }),
+    }))
+}
+
+/// The plugin type of a datasource reference, when it names one. Older dashboards
+/// refer to datasources by name only, which is assumed to be Prometheus, and mixed
+/// panels name one per target.
+fn datasource_type(datasource: Option<&Value>) -> Option<&str> {
+    let datasource = datasource?;
+    if datasource.get("uid").and_then(Value::as_str) == Some("-- Mixed --") {
+        return None;
+    }
+    datasource.get("type")?.as_str()
+}
+
+/// Current values of the dashboard's template variables, formatted the way
+/// Grafana interpolates them into PromQL.
+fn variables(templating: &Templating) -> BTreeMap<String, String> {
+    templating
+        .list
+        .iter()
+        .filter(|v| v.kind != "datasource")
+        .filter_map(|v| {
+            let value = match v.kind.as_str() {
+                "constant" => v.query.clone(),
+                _ => v.current.as_ref().and_then(|c| c.value.clone()),
+            }?;
+            let value = match value {
+                Value::String(s) if s == "$__all" => v.all_value.clone().unwrap_or(".*".into()),
+                Value::String(s) => s,
+                Value::Array(values) => {
+                    let values: Vec<String> = values
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|s| {
+                            if s == "$__all" {
+                                v.all_value.clone().unwrap_or(".*".into())
+                            } else {
+                                escape_regex(s)
+                            }
+                        })
+                        .collect();
+                    match values.as_slice() {
+                        [single] => single.clone(),
+                        _ => format!("({})", values.join("|")),
+                    }
+                }
+                _ => return None,
+            };
+            Some((v.name.clone(), value))
+        })
+        .collect()
+}
+
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Byte offset of a 1-based line and column, as reported by serde_json.
+fn line_offset(text: &str, line: usize, column: usize) -> usize {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (start + column.saturating_sub(1)).min(text.len())
+}
@@ -0,0 +1,269 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use miette::{Result, miette};
+
+use crate::{promql::RangeSeries, series::timestamp_key, units::Unit};
+
+/// Colour stops of the ramp, from few observations to many.
+const RAMP: &[(u8, u8, u8)] = &[
+    (13, 8, 135),
+    (126, 3, 168),
+    (204, 71, 120),
+    (248, 149, 64),
+    (240, 249, 33),
+];
+
+/// Observations per histogram bucket over time.
+pub struct Heatmap {
+    /// Upper bounds of the buckets, ascending, the last one usually `+Inf`
+    pub bounds: Vec<f64>,
+    pub timestamps: Vec<f64>,
+    /// Observations in each bucket at each timestamp, `counts[time][bucket]`
+    pub counts: Vec<Vec<f64>>,
+}
+
+impl Heatmap {
+    /// Builds a heatmap from native histograms when the series have any, otherwise
+    /// from the `le`-labelled series of a classic histogram.
+    pub fn from_series(data: &[(String, RangeSeries)]) -> Result<Self> {
+        if data.iter().any(|(_, v)| !v.histograms().is_empty()) {
+            return Self::from_native(data);
+        }
+        Self::from_buckets(data)
+    }
+
+    /// Series with the same bound are summed, then the cumulative buckets are split
+    /// into the observations falling between consecutive bounds.
+    fn from_buckets(data: &[(String, RangeSeries)]) -> Result<Self> {
+        let mut timestamps = BTreeMap::new();
+        let mut cumulative: Vec<(f64, BTreeMap<i64, f64>)> = Vec::new();
+
+        for (_, v) in data {
+            let Some(bound) = v.metric().get("le").and_then(|le| le.parse::<f64>().ok()) else {
+                continue;
+            };
+            let index = match cumulative.iter().position(|(b, _)| *b == bound) {
+                Some(index) => index,
+                None => {
+                    cumulative.push((bound, BTreeMap::new()));
+                    cumulative.len() - 1
+                }
+            };
+            for s in v.samples() {
+                timestamps.insert(timestamp_key(s.timestamp()), s.timestamp());
+                if s.value().is_finite() {
+                    *cumulative[index]
+                        .1
+                        .entry(timestamp_key(s.timestamp()))
+                        .or_default() += s.value();
+                }
+            }
+        }
+
+        if cumulative.is_empty() {
+            return Err(miette!(
+                help = "query a native histogram, or the buckets of a classic one, e.g. \
+                    sum by (le) (rate(http_request_duration_seconds_bucket[5m]))",
+                "no histogram found, the series are neither native histograms nor have an `le` label"
+            ));
+        }
+        cumulative.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let counts = timestamps
+            .keys()
+            .map(|ts| {
+                let mut below = 0.0;
+                cumulative
+                    .iter()
+                    .map(|(_, values)| {
+                        let total = values.get(ts).copied().unwrap_or(below);
+                        // Buckets scraped at slightly different times can dip below zero
+                        let count = (total - below).max(0.0);
+                        below = f64::max(below, total);
+                        count
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            bounds: cumulative.iter().map(|(bound, _)| *bound).collect(),
+            timestamps: timestamps.into_values().collect(),
+            counts,
+        })
+    }
+
+    /// Native histograms may change their buckets between samples, so every bucket
+    /// any sample has becomes a row, keyed by its upper bound. Histograms of
+    /// different series are summed. Idle histograms have no buckets at all, which
+    /// leaves nothing to draw.
+    fn from_native(data: &[(String, RangeSeries)]) -> Result<Self> {
+        let histograms = || data.iter().flat_map(|(_, v)| v.histograms());
+
+        let mut bounds: Vec<f64> = histograms()
+            .flat_map(|h| h.buckets.iter().map(|b| b.upper))
+            .collect();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        if bounds.is_empty() {
+            return Err(miette!(
+                help = "widen the time range to one with observations",
+                "no histogram buckets found, the native histograms have no observations"
+            ));
+        }
+
+        let mut counts: BTreeMap<i64, (f64, Vec<f64>)> = BTreeMap::new();
+        for h in histograms() {
+            let (_, row) = counts
+                .entry(timestamp_key(h.timestamp))
+                .or_insert_with(|| (h.timestamp, vec![0.0; bounds.len()]));
+            for bucket in &h.buckets {
+                if let Ok(index) = bounds.binary_search_by(|b| b.total_cmp(&bucket.upper))
+                    && bucket.count.is_finite()
+                {
+                    row[index] += bucket.count;
+                }
+            }
+        }
+
+        let (timestamps, counts) = counts.into_values().unzip();
+        Ok(Self {
+            bounds,
+            timestamps,
+            counts,
+        })
+    }
+
+    /// Upper bound of a bucket, as shown on the bucket axis.
+    pub fn bucket_label(&self, bucket: usize, unit: Unit) -> String {
+        match self.bounds[bucket] {
+            bound if bound.is_infinite() => "+Inf".to_string(),
+            bound => unit.format(bound),
+        }
+    }
+
+    /// Buckets drawn in `row` of a grid `rows` high, row 0 being the lowest. Empty
+    /// without buckets.
+    pub fn buckets_in(&self, row: usize, rows: usize) -> Range<usize> {
+        span(row, rows, self.bounds.len())
+    }
+
+    /// Samples drawn in `column` of a grid `columns` wide.
+    pub fn samples_in(&self, column: usize, columns: usize) -> Range<usize> {
+        span(column, columns, self.timestamps.len())
+    }
+
+    /// Fits the counts into a `columns` × `rows` grid, `grid[column][row]`. Cells hold
+    /// the sum of their buckets averaged over their samples, so merging buckets
+    /// adds up observations while merging samples does not.
+    pub fn resample(&self, columns: usize, rows: usize) -> Vec<Vec<f64>> {
+        (0..columns)
+            .map(|column| {
+                let samples = self.samples_in(column, columns);
+                (0..rows)
+                    .map(|row| {
+                        let buckets = self.buckets_in(row, rows);
+                        let total: f64 = self.counts[samples.clone()]
+                            .iter()
+                            .map(|counts| counts[buckets.clone()].iter().sum::<f64>())
+                            .sum();
+                        total / samples.len().max(1) as f64
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Items of `len` shown in `cell` of `cells`, every cell getting at least one item.
+fn span(cell: usize, cells: usize, len: usize) -> Range<usize> {
+    let start = (cell * len / cells.max(1)).min(len.saturating_sub(1));
+    let end = ((cell + 1) * len / cells.max(1)).clamp(start + 1, len.max(1));
+    start..end.min(len)
+}
+
+/// Colour of a cell holding `value` when the fullest one holds `max`, or `None`
+/// for empty cells. The square root keeps sparse buckets visible beside busy ones.
+pub fn color(value: f64, max: f64) -> Option<(u8, u8, u8)> {
+    if value <= 0.0 || max <= 0.0 {
+        return None;
+    }
+    Some(ramp((value / max).sqrt()))
+}
+
+/// Interpolates the colour ramp at `fraction` between 0 and 1.
+pub fn ramp(fraction: f64) -> (u8, u8, u8) {
+    let position = fraction.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let index = (position.floor() as usize).min(RAMP.len() - 2);
+    let t = position - index as f64;
+    let (from, to) = (RAMP[index], RAMP[index + 1]);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// The largest cell of a grid from `Heatmap::resample`.
+pub fn max(grid: &[Vec<f64>]) -> f64 {
+    grid.iter().flatten().copied().fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::promql;
+
+    fn series(result: &str) -> Vec<(String, RangeSeries)> {
+        let body = format!(
+            r#"{{"status":"success","data":{{"resultType":"matrix","result":[{}]}}}}"#,
+            result
+        );
+        promql::parse_range_response(body.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|v| (String::new(), v))
+            .collect()
+    }
+
+    #[test]
+    fn from_native_sums_buckets() {
+        let data = series(
+            r#"{"metric":{},"histograms":[
+                [1000,{"count":"3","sum":"2","buckets":[[0,"0","1","1"],[0,"1","2","2"]]}],
+                [1060,{"count":"2","sum":"1","buckets":[[0,"1","2","2"]]}]]}"#,
+        );
+        let heatmap = Heatmap::from_series(&data).unwrap();
+        assert_eq!(heatmap.bounds, vec![1.0, 2.0]);
+        assert_eq!(heatmap.timestamps, vec![1000.0, 1060.0]);
+        assert_eq!(heatmap.counts, vec![vec![1.0, 2.0], vec![0.0, 2.0]]);
+    }
+
+    #[test]
+    fn from_native_without_buckets() {
+        let data = series(
+            r#"{"metric":{},"histograms":[
+                [1000,{"count":"0","sum":"0"}],
+                [1060,{"count":"0","sum":"0"}]]}"#,
+        );
+        assert!(Heatmap::from_series(&data).is_err());
+    }
+
+    #[test]
+    fn from_buckets_splits_cumulative_counts() {
+        let data = series(
+            r#"{"metric":{"le":"1"},"values":[[1000,"2"]]},
+               {"metric":{"le":"+Inf"},"values":[[1000,"5"]]}"#,
+        );
+        let heatmap = Heatmap::from_series(&data).unwrap();
+        assert_eq!(heatmap.bounds, vec![1.0, f64::INFINITY]);
+        assert_eq!(heatmap.counts, vec![vec![2.0, 3.0]]);
+    }
+
+    #[test]
+    fn buckets_in_without_buckets() {
+        let heatmap = Heatmap {
+            bounds: Vec::new(),
+            timestamps: Vec::new(),
+            counts: Vec::new(),
+        };
+        assert!(heatmap.buckets_in(0, 4).is_empty());
+    }
+}
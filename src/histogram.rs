@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+use crate::promql::{self, RangeSeries, Sample};
+
+/// Quantiles drawn for native histograms unless `--quantiles` picks others.
+pub const DEFAULT_QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// A native histogram sample, as returned in the `histograms` of a range query.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(from = "(f64, RawHistogram)", into = "(f64, RawHistogram)")]
+pub struct HistogramSample {
+    pub timestamp: f64,
+    pub count: f64,
+    /// Not drawn by any chart, only exported
+    pub sum: f64,
+    /// Buckets with observations, ascending
+    pub buckets: Vec<Bucket>,
+}
+
+/// Observations between two bounds. Which bounds are inclusive does not matter at
+/// the resolution of a chart, it is only kept for exports.
+#[derive(Clone, Copy, Debug)]
+pub struct Bucket {
+    /// The API's boundary rule: 0 open left, 1 open right, 2 open both, 3 closed both
+    pub boundaries: u8,
+    pub lower: f64,
+    pub upper: f64,
+    pub count: f64,
+}
+
+/// The API's representation: numbers as strings, buckets as
+/// `[boundary rule, lower, upper, count]` arrays.
+#[derive(Deserialize, Serialize)]
+pub struct RawHistogram {
+    count: String,
+    sum: String,
+    #[serde(default)]
+    buckets: Vec<(u8, String, String, String)>,
+}
+
+impl From<(f64, RawHistogram)> for HistogramSample {
+    fn from((timestamp, raw): (f64, RawHistogram)) -> Self {
+        let number = |s: &str| s.parse().unwrap_or(f64::NAN);
+        Self {
+            timestamp,
+            count: number(&raw.count),
+            sum: number(&raw.sum),
+            buckets: raw
+                .buckets
+                .iter()
+                .map(|(boundaries, lower, upper, count)| Bucket {
+                    boundaries: *boundaries,
+                    lower: number(lower),
+                    upper: number(upper),
+                    count: number(count),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<HistogramSample> for (f64, RawHistogram) {
+    fn from(h: HistogramSample) -> Self {
+        (h.timestamp, h.to_raw())
+    }
+}
+
+impl HistogramSample {
+    /// The sample in the API's representation, without its timestamp.
+    pub fn to_raw(&self) -> RawHistogram {
+        let number = promql::format_value;
+        RawHistogram {
+            count: number(self.count),
+            sum: number(self.sum),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|b| {
+                    (
+                        b.boundaries,
+                        number(b.lower),
+                        number(b.upper),
+                        number(b.count),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Estimates the `q` quantile like `histogram_quantile()`, interpolating
+    /// linearly within the bucket the quantile falls in.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count.is_nan() || self.count <= 0.0 || q.is_nan() {
+            return f64::NAN;
+        }
+        let rank = q.clamp(0.0, 1.0) * self.count;
+
+        let mut below = 0.0;
+        for bucket in &self.buckets {
+            if below + bucket.count >= rank && bucket.count > 0.0 {
+                if bucket.upper.is_infinite() {
+                    return bucket.lower;
+                }
+                if bucket.lower.is_infinite() {
+                    return bucket.upper;
+                }
+                let fraction = (rank - below) / bucket.count;
+                return bucket.lower + (bucket.upper - bucket.lower) * fraction;
+            }
+            below += bucket.count;
+        }
+        self.buckets.last().map_or(f64::NAN, |b| b.upper)
+    }
+}
+
+/// Parses a `--quantiles` value.
+pub fn parse_quantile(arg: &str) -> Result<f64, String> {
+    match arg.trim().parse::<f64>() {
+        Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+        _ => Err("quantiles are numbers between 0 and 1, like 0.99".to_string()),
+    }
+}
+
+/// Replaces the histogram samples of each series by a float series per quantile,
+/// labelled like `<series> p99`. Float samples are kept as they are.
+pub fn quantile_lines(
+    data: Vec<(String, RangeSeries)>,
+    quantiles: &[f64],
+) -> Vec<(String, RangeSeries)> {
+    let mut result = Vec::new();
+    for (label, series) in data {
+        if series.histograms().is_empty() {
+            result.push((label, series));
+            continue;
+        }
+        for &q in quantiles {
+            let samples = series
+                .histograms()
+                .iter()
+                .map(|h| Sample::new(h.timestamp, h.quantile(q)))
+                .collect();
+            result.push((
+                format!("{} p{}", label, (q * 1000.0).round() / 10.0),
+                series.with_samples(samples),
+            ));
+        }
+        if !series.samples().is_empty() {
+            result.push((label, series.with_samples(series.samples().to_vec())));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(buckets: &[(f64, f64, f64)]) -> HistogramSample {
+        HistogramSample {
+            timestamp: 0.0,
+            count: buckets.iter().map(|&(_, _, count)| count).sum(),
+            sum: 0.0,
+            buckets: buckets
+                .iter()
+                .map(|&(lower, upper, count)| Bucket {
+                    boundaries: 0,
+                    lower,
+                    upper,
+                    count,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn quantile_interpolates_within_bucket() {
+        let h = histogram(&[(0.0, 1.0, 2.0), (1.0, 2.0, 2.0)]);
+        assert_eq!(h.quantile(0.5), 1.0);
+        assert_eq!(h.quantile(0.75), 1.5);
+        assert_eq!(h.quantile(1.0), 2.0);
+        assert_eq!(h.quantile(0.0), 0.0);
+    }
+
+    #[test]
+    fn quantile_skips_empty_buckets() {
+        let h = histogram(&[(0.0, 1.0, 0.0), (1.0, 2.0, 4.0)]);
+        assert_eq!(h.quantile(0.0), 1.0);
+        assert_eq!(h.quantile(0.5), 1.5);
+    }
+
+    #[test]
+    fn quantile_in_infinite_buckets() {
+        // The bound that is finite, as histogram_quantile() does
+        let h = histogram(&[(0.0, 1.0, 1.0), (1.0, f64::INFINITY, 3.0)]);
+        assert_eq!(h.quantile(0.99), 1.0);
+        let h = histogram(&[(f64::NEG_INFINITY, -1.0, 3.0), (-1.0, 0.0, 1.0)]);
+        assert_eq!(h.quantile(0.1), -1.0);
+    }
+
+    #[test]
+    fn quantile_without_observations() {
+        assert!(histogram(&[]).quantile(0.5).is_nan());
+        assert!(histogram(&[(0.0, 1.0, 0.0)]).quantile(0.5).is_nan());
+        assert!(histogram(&[(0.0, 1.0, 1.0)]).quantile(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn parse_quantile_range() {
+        assert_eq!(parse_quantile("0.99"), Ok(0.99));
+        assert_eq!(parse_quantile("1"), Ok(1.0));
+        for value in ["1.5", "-0.1", "p99", ""] {
+            assert!(parse_quantile(value).is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use serde::Deserialize;
+
+use crate::{
+    promql::{self, Query, RangeSeries},
+    timerange::TimeRange,
+};
+
+/// A series of a `--format json` export, under the name it was shown with.
+#[derive(Deserialize)]
+struct NamedSeries {
+    name: String,
+    #[serde(flatten)]
+    series: RangeSeries,
+}
+
+/// Reads series from `path`, or stdin for `-`: either a `--format json` export or a
+/// raw `/api/v1/query_range` response body, whose series are labelled by `query`.
+pub fn read(path: &Path, query: &Query) -> Result<Vec<(String, RangeSeries)>> {
+    let source = if path == Path::new("-") {
+        "stdin".to_string()
+    } else {
+        path.display().to_string()
+    };
+    let body = if path == Path::new("-") {
+        let mut body = Vec::new();
+        io::stdin()
+            .read_to_end(&mut body)
+            .into_diagnostic()
+            .wrap_err("failed to read stdin")?;
+        body
+    } else {
+        fs::read(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?
+    };
+
+    // Exports are a bare array, responses an object with a status
+    let data: Vec<(String, RangeSeries)> = if body.trim_ascii_start().starts_with(b"[") {
+        let series: Vec<NamedSeries> = serde_json::from_slice(&body)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("invalid promegraph export {}", source))?;
+        series.into_iter().map(|s| (s.name, s.series)).collect()
+    } else {
+        promql::parse_range_response(&body)
+            .wrap_err_with(|| format!("failed to read {}", source))?
+            .into_iter()
+            .map(|v| (query.label(v.metric()), v))
+            .collect()
+    };
+
+    if data.is_empty() {
+        return Err(miette!("no series in {}", source));
+    }
+    Ok(data)
+}
+
+/// The window the samples cover, standing in for `--start` and `--end`.
+pub fn range(data: &[(String, RangeSeries)]) -> TimeRange {
+    let timestamps = data.iter().flat_map(|(_, v)| {
+        let samples = v.samples().iter().map(|s| s.timestamp());
+        samples.chain(v.histograms().iter().map(|h| h.timestamp))
+    });
+    let (start, end) = timestamps.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), ts| {
+        (min.min(ts), max.max(ts))
+    });
+    if start > end {
+        return TimeRange::fixed(0, 1);
+    }
+    let start = start.floor() as i64;
+    TimeRange::fixed(start, (end.ceil() as i64).max(start + 1))
+}
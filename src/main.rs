@@ -1,70 +1,515 @@
-use clap::{Parser, ValueEnum};
-use miette::Result;
+use std::{
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+};
 
+use clap::{Parser, Subcommand, ValueEnum};
+use miette::{IntoDiagnostic, Result, miette};
+use serde::Deserialize;
+
+mod backend_plain;
+mod backend_plotters;
 mod backend_ratatui;
+mod backend_sparkline;
 mod backend_textplots;
+mod client;
+mod config;
+mod dashboard;
+mod export;
+mod grafana;
+mod heatmap;
+mod histogram;
+mod input;
 mod promql;
+mod series;
+mod style;
+mod terminal;
+mod timerange;
+mod units;
+mod variables;
+
+use backend_plotters::OutputFormat;
+use backend_ratatui::{ChartOptions, Mode};
+use backend_sparkline::SortBy;
+use client::ClientArgs;
+use config::{Config, Datasource, SavedQuery};
+use dashboard::{Dashboard, DashboardView};
+use export::Format;
+use heatmap::Heatmap;
+use prometheus_http_query::Client;
+use promql::{Query, RangeSeries, get_data, get_instant};
+use series::{Frame, Gaps, NullAs, Renderer};
+use style::ChartStyle;
+use terminal::ColorChoice;
+use timerange::{Step, TimeRange};
+use units::Unit;
 
-use promql::get_data;
+const DEFAULT_ADDR: &str = "http://localhost:8428/";
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
 enum Backend {
+    Plain,
     Plotters,
     Textplots,
     Ratatui,
+    Sparkline,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show a grid of panels from a TOML or YAML dashboard definition
+    Dashboard {
+        /// Dashboard file (.toml, .yaml or .yml)
+        file: PathBuf,
+    },
+    /// Show a Grafana dashboard JSON export as a terminal dashboard
+    GrafanaImport {
+        /// Dashboard JSON, as exported from Grafana or fetched from its HTTP API
+        file: PathBuf,
+    },
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    /// Promql expression
-    expr: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Prometheus server address
-    #[arg(short, long, default_value = "http://localhost:8428/")]
-    addr: String,
+    /// Promql expressions drawn together on one chart, or @name for a saved query
+    #[arg(required_unless_present_any = ["expr", "input"])]
+    exprs: Vec<String>,
 
-    /// Step
-    #[arg(short, long, default_value_t = 15.0)]
-    step: f64,
+    /// Additional promql expression, can be repeated
+    #[arg(short, long)]
+    expr: Vec<String>,
+
+    /// Legend format per expression in order, e.g. "{{handler}} 5xx"
+    #[arg(short, long)]
+    legend: Vec<String>,
+
+    /// Prometheus server address [default: http://localhost:8428/]
+    #[arg(short, long, global = true)]
+    addr: Option<String>,
+
+    /// Named datasource from the config file
+    #[arg(long, global = true)]
+    datasource: Option<String>,
+
+    /// Config file [default: $XDG_CONFIG_HOME/promegraph/config.toml]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Step: auto, seconds, or a duration like 30s or 5m [default: auto]
+    #[arg(short, long, global = true)]
+    step: Option<String>,
+
+    /// Draw series from a saved query_range response or a --format json export
+    /// instead of querying, - for stdin
+    #[arg(long, conflicts_with_all = ["exprs", "expr", "instant"])]
+    input: Option<PathBuf>,
+
+    /// Run an instant query instead of a range query
+    #[arg(short, long)]
+    instant: bool,
+
+    /// Draw histogram `_bucket` series as a heatmap of observations per bucket over time
+    #[arg(long, conflicts_with = "instant")]
+    heatmap: bool,
+
+    /// Quantiles drawn for native histograms, comma separated [default: 0.5,0.9,0.99]
+    #[arg(long, value_delimiter = ',', value_parser = histogram::parse_quantile)]
+    quantiles: Vec<f64>,
+
+    /// Duration of the range up to --end, e.g. 90s, 15m, 6h, 7d (bare numbers are minutes);
+    /// --start overrides it [default: 1m]
+    #[arg(short, long, global = true)]
+    duration: Option<String>,
+
+    /// Start of the range: RFC 3339, Unix seconds, now-3h, yesterday 14:00, ...
+    #[arg(long, global = true)]
+    start: Option<String>,
+
+    /// End of the range, same formats as --start (defaults to now)
+    #[arg(long, global = true)]
+    end: Option<String>,
 
-    /// Duration in minutes
-    #[arg(short, long, default_value_t = 1)]
-    duration: u16,
+    /// Template variable as name=value, used as $name in expressions, can be repeated
+    #[arg(long = "var", value_name = "NAME=VALUE", global = true)]
+    vars: Vec<String>,
 
-    #[arg(short,value_enum, default_value_t = Backend::Textplots)]
-    backend: Backend,
+    /// Unit of the values, e.g. bytes, seconds, percentunit [default: inferred from metric names]
+    #[arg(long, value_enum)]
+    unit: Option<Unit>,
 
-    /// Refresh interval in seconds (ratatui backend only)
-    #[arg(short, long, default_value_t = 30)]
+    /// How series are drawn; stacked sums them on top of each other [default: line]
+    #[arg(long, value_enum)]
+    style: Option<ChartStyle>,
+
+    /// How intervals without samples are drawn [default: gap]
+    #[arg(long, value_enum, global = true)]
+    null_as: Option<NullAs>,
+
+    /// Intervals between samples longer than this many steps count as missing samples
+    #[arg(long, value_name = "STEPS", default_value_t = series::DEFAULT_MAX_GAP,
+          value_parser = series::parse_max_gap, global = true)]
+    max_gap: f64,
+
+    /// Write the series in a machine-readable format to stdout instead of drawing them
+    #[arg(long, value_enum, conflicts_with_all = ["instant", "heatmap"])]
+    format: Option<Format>,
+
+    /// Lay csv and tsv out with a column per series instead of a row per sample
+    #[arg(long, requires = "format")]
+    wide: bool,
+
+    /// Rendering backend [default: textplots]
+    #[arg(short, value_enum)]
+    backend: Option<Backend>,
+
+    /// Width of the output in columns, or pixels for plotters [default: the terminal's]
+    #[arg(long)]
+    width: Option<u16>,
+
+    /// Height of the output in lines, or pixels for plotters [default: the terminal's]
+    #[arg(long)]
+    height: Option<u16>,
+
+    /// Colour the output: auto is when stdout is a terminal and NO_COLOR is not set
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorChoice,
+
+    /// Order of the series, largest first for statistics (sparkline backend only)
+    /// [default: as queried]
+    #[arg(long, value_enum)]
+    sort: Option<SortBy>,
+
+    /// Refresh interval in seconds (ratatui backend and dashboards)
+    #[arg(short, long, default_value_t = 30, global = true)]
     refresh: u64,
+
+    /// Output file, .png or .svg (plotters backend only)
+    #[arg(short, long, default_value = "promegraph.png")]
+    output: PathBuf,
+
+    #[command(flatten)]
+    client: ClientArgs,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    match args.backend {
-        Backend::Plotters => {
-            println!("using plotters");
-            get_data(&args.addr, &args.expr, args.step, args.duration).await?;
+    let config = Config::load(args.config.as_deref())?;
+    match &args.command {
+        Some(Command::Dashboard { file }) => {
+            return run_dashboard(&args, &config, Dashboard::load(file)?).await;
+        }
+        Some(Command::GrafanaImport { file }) => {
+            let overrides = variables::parse_args(&args.vars)?;
+            let (dashboard, warnings) = grafana::import(file, &overrides)?;
+            for warning in warnings {
+                eprintln!("{:?}", warning);
+            }
+            return run_dashboard(&args, &config, dashboard).await;
+        }
+        None => {}
+    }
+
+    let (mut queries, saved) = queries(&args, &config)?;
+
+    // Command line flags win over the saved query, which wins over the datasource
+    let datasource = datasource(&args, &config, saved.and_then(|q| q.datasource.as_deref()))?;
+    let duration = args
+        .duration
+        .as_deref()
+        .or(saved.and_then(|q| q.duration.as_deref()))
+        .unwrap_or("1m");
+    let step = args
+        .step
+        .as_deref()
+        .or(saved.and_then(|q| q.step.as_deref()))
+        .or(datasource.and_then(|d| d.step.as_deref()))
+        .unwrap_or("auto");
+    let backend = args
+        .backend
+        .or(saved.and_then(|q| q.backend))
+        .unwrap_or(Backend::Textplots);
+    // Checked before anything is queried, for nothing would be drawn
+    if matches!(backend, Backend::Sparkline) && (args.instant || args.heatmap) {
+        return Err(miette!(
+            help = "use the textplots or ratatui backend",
+            "{} is not supported by the sparkline backend",
+            if args.instant {
+                "--instant"
+            } else {
+                "--heatmap"
+            }
+        ));
+    }
+
+    // Likewise, a typo in -o fails before a query is wasted on it
+    let output_format = match backend {
+        Backend::Plotters => Some(OutputFormat::from_path(&args.output)?),
+        _ => None,
+    };
+
+    // A file stands in for the queries, its name for their expressions
+    let input = match &args.input {
+        Some(path) => {
+            let query = Query {
+                expr: path.display().to_string(),
+                legend: args.legend.first().cloned(),
+            };
+            let data = input::read(path, &query)?;
+            queries = vec![query];
+            Some(data)
         }
+        None => None,
+    };
+    let range = match &input {
+        Some(data) => input::range(data),
+        None => TimeRange::from_args(args.start.as_deref(), args.end.as_deref(), duration)?,
+    };
+    let step = Step::from_arg(step)?;
+    let client = connect(&args, datasource)?;
+
+    let mut variables = variables::from_args(&args.vars, &config.variables, &queries)?;
+    variables::resolve(&client, &mut variables, &range).await?;
+    let templates = queries;
+    let queries = variables::apply(&templates, &variables);
+    let unit = args
+        .unit
+        .or(saved.and_then(|q| q.unit))
+        .unwrap_or_else(|| match &input {
+            Some(data) => Unit::infer_series(data),
+            None => Unit::infer(&queries),
+        });
+    let quantiles = match args.quantiles.as_slice() {
+        [] => histogram::DEFAULT_QUANTILES,
+        quantiles => quantiles,
+    };
+    let style = args
+        .style
+        .or(saved.and_then(|q| q.style))
+        .unwrap_or_default();
+
+    let gaps = gaps(&args);
+
+    if let Some(format) = args.format {
+        let step = step.seconds(&range, export::POINTS);
+        let data = range_data(&input, &client, &queries, step, &range).await?;
+        return export::write(&mut std::io::stdout().lock(), format, args.wide, &data);
+    }
+
+    let renderer: Box<dyn Renderer> = match backend {
+        Backend::Plain => Box::new(backend_plain::BackendPlain::new(unit)),
+        Backend::Plotters => Box::new(backend_plotters::BackendPlotters::new(
+            args.output.clone(),
+            output_format.expect("output format checked with the backend"),
+            args.width.map_or(1280, u32::from),
+            args.height.map_or(720, u32::from),
+            unit,
+            style,
+        )),
         Backend::Textplots => {
-            let data = get_data(&args.addr, &args.expr, args.step, args.duration).await?;
-            let backend = backend_textplots::BackendTextplots::new(200, 60);
-            let result = backend.generate(data)?;
-            println!("{}", result);
+            let (columns, rows) = terminal::size();
+            Box::new(backend_textplots::BackendTextplots::new(
+                args.width.unwrap_or(columns),
+                args.height.unwrap_or(rows),
+                unit,
+                style,
+                args.color.enabled(),
+            ))
         }
         Backend::Ratatui => {
+            let mode = if args.instant {
+                Mode::Instant
+            } else if args.heatmap {
+                Mode::Heatmap
+            } else {
+                Mode::Range
+            };
+            let options = ChartOptions {
+                mode,
+                step,
+                quantiles: quantiles.to_vec(),
+                unit,
+                style,
+                gaps,
+            };
             let backend = backend_ratatui::BackendRatatui::new(
-                args.addr,
-                args.expr,
-                args.step,
-                args.duration,
+                client.clone(),
+                templates,
+                variables,
+                range.clone(),
                 args.refresh,
+                options,
+                input.clone(),
             );
-            backend.run().await?;
+            if io::stdout().is_terminal() {
+                return backend.run().await;
+            }
+            Box::new(backend)
+        }
+        Backend::Sparkline => {
+            let (columns, _) = terminal::size();
+            Box::new(backend_sparkline::BackendSparkline::new(
+                args.width.unwrap_or(columns),
+                unit,
+                args.sort,
+            ))
+        }
+    };
+
+    let title = promql::title(&queries);
+    let result = if args.instant {
+        renderer.render_instant(&title, get_instant(&client, &queries, &range).await?)?
+    } else if args.heatmap {
+        let step = step.seconds(&range, renderer.heatmap_points());
+        let data = range_data(&input, &client, &queries, step, &range).await?;
+        renderer.render_heatmap(&title, &Heatmap::from_series(&data)?)?
+    } else {
+        // Resolved once, so the chart spans exactly the window queried
+        let (start, end) = range.bounds();
+        let range = TimeRange::fixed(start, end);
+        let step = step.seconds(&range, renderer.points());
+        let data = range_data(&input, &client, &queries, step, &range).await?;
+        let data = histogram::quantile_lines(data, quantiles);
+        let mut frame = Frame::from_range(&data);
+        frame.range = Some((start as f64, end as f64));
+        frame.mark_gaps(gaps);
+        renderer.render(&title, &frame)?
+    };
+    print(&result)
+}
+
+/// Writes the rendered output to stdout. A reader closing the pipe early, like `head`
+/// does, is not an error.
+fn print(output: &str) -> Result<()> {
+    match writeln!(io::stdout().lock(), "{}", output) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.into_diagnostic(),
+    }
+}
+
+async fn run_dashboard(args: &Args, config: &Config, mut dashboard: Dashboard) -> Result<()> {
+    let datasource = datasource(args, config, dashboard.datasource.as_deref())?;
+    let duration = args
+        .duration
+        .as_deref()
+        .or(dashboard.duration.as_deref())
+        .unwrap_or("1h");
+    let step = args
+        .step
+        .as_deref()
+        .or(datasource.and_then(|d| d.step.as_deref()))
+        .unwrap_or("auto");
+
+    let range = TimeRange::from_args(args.start.as_deref(), args.end.as_deref(), duration)?;
+    let step = Step::from_arg(step)?;
+    let client = connect(args, datasource)?;
+
+    let mut variables = variables::from_args(&args.vars, &config.variables, &dashboard.queries())?;
+    variables::resolve(&client, &mut variables, &range).await?;
+    dashboard.apply(&variables);
+
+    // Defaults of the panels, which pick their own type, unit and style
+    let options = ChartOptions {
+        mode: Mode::Range,
+        step,
+        quantiles: histogram::DEFAULT_QUANTILES.to_vec(),
+        unit: Unit::None,
+        style: ChartStyle::default(),
+        gaps: gaps(args),
+    };
+    DashboardView::new(client, dashboard, range, args.refresh, options)
+        .run()
+        .await
+}
+
+/// The series read from `--input`, or else the results of the range queries.
+async fn range_data(
+    input: &Option<Vec<(String, RangeSeries)>>,
+    client: &Client,
+    queries: &[Query],
+    step: f64,
+    range: &TimeRange,
+) -> Result<Vec<(String, RangeSeries)>> {
+    match input {
+        Some(data) => Ok(data.clone()),
+        None => get_data(client, queries, step, range).await,
+    }
+}
+
+/// Picks the datasource named on the command line, else `fallback`, else the config default.
+fn datasource<'a>(
+    args: &Args,
+    config: &'a Config,
+    fallback: Option<&str>,
+) -> Result<Option<&'a Datasource>> {
+    let name = args
+        .datasource
+        .as_deref()
+        .or(fallback)
+        .or(config.default_datasource.as_deref());
+    name.map(|name| config.datasource(name)).transpose()
+}
+
+/// How gaps in series are drawn.
+fn gaps(args: &Args) -> Gaps {
+    Gaps {
+        null_as: args.null_as.unwrap_or_default(),
+        max_gap: args.max_gap,
+    }
+}
+
+fn connect(args: &Args, datasource: Option<&Datasource>) -> Result<Client> {
+    let addr = args
+        .addr
+        .as_deref()
+        .or(datasource.and_then(|d| d.address.as_deref()))
+        .unwrap_or(DEFAULT_ADDR);
+    let client_args = match datasource {
+        Some(datasource) => args.client.clone().or(&datasource.client),
+        None => args.client.clone(),
+    };
+    client::build_client(addr, &client_args)
+}
+
+/// Collects the expressions to graph, expanding `@name` saved queries. Also returns the
+/// first saved query, whose defaults apply to the whole invocation.
+fn queries<'a>(args: &Args, config: &'a Config) -> Result<(Vec<Query>, Option<&'a SavedQuery>)> {
+    let exprs: Vec<&String> = args.exprs.iter().chain(args.expr.iter()).collect();
+    // An --input file takes a legend like an expression does
+    let expected = exprs.len() + usize::from(args.input.is_some());
+    if args.legend.len() > expected {
+        return Err(miette!(
+            help = "pass at most one --legend per expression, in the same order",
+            "{} legend formats given for {} expressions",
+            args.legend.len(),
+            expected
+        ));
+    }
+
+    let mut first_saved = None;
+    let mut queries = Vec::new();
+    for (i, expr) in exprs.into_iter().enumerate() {
+        let legend = args.legend.get(i).cloned();
+        match expr.strip_prefix('@') {
+            Some(name) => {
+                let saved = config.query(name)?;
+                first_saved = first_saved.or(Some(saved));
+                queries.push(Query {
+                    expr: saved.expr.clone(),
+                    legend: legend.or_else(|| saved.legend.clone()),
+                });
+            }
+            None => queries.push(Query {
+                expr: expr.clone(),
+                legend,
+            }),
         }
     }
 
-    Ok(())
+    Ok((queries, first_saved))
 }
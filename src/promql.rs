@@ -1,29 +1,360 @@
-use jiff::Timestamp;
-use miette::{IntoDiagnostic, Result, miette};
-use prometheus_http_query::{Client, response::RangeVector};
+use std::collections::{BTreeMap, HashMap};
 
+use miette::{IntoDiagnostic, Result, WrapErr, miette};
+use prometheus_http_query::{Client, response::Data};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{histogram::HistogramSample, timerange::TimeRange};
+
+/// An expression to graph, with an optional Grafana-style legend format.
+#[derive(Clone, Debug)]
+pub struct Query {
+    pub expr: String,
+    pub legend: Option<String>,
+}
+
+impl Query {
+    /// Display label for one of this query's series.
+    pub fn label(&self, metric: &HashMap<String, String>) -> String {
+        match &self.legend {
+            Some(legend) => legend_label(legend, metric),
+            // Aggregations without labels would otherwise all be called `{}`
+            None if metric.is_empty() => self.expr.clone(),
+            None => series_label(metric),
+        }
+    }
+}
+
+/// A series returned by a range query. Native histograms come as histogram samples,
+/// instead of or next to float samples. Serializes back to the API's shape.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RangeSeries {
+    #[serde(serialize_with = "sorted")]
+    metric: HashMap<String, String>,
+    #[serde(default, rename = "values", skip_serializing_if = "Vec::is_empty")]
+    samples: Vec<Sample>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    histograms: Vec<HistogramSample>,
+}
+
+/// Writes labels in name order, so that exports do not change from run to run.
+fn sorted<S: Serializer>(
+    metric: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    metric
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+impl RangeSeries {
+    /// The labels of the series, including `__name__` when it has one.
+    pub fn metric(&self) -> &HashMap<String, String> {
+        &self.metric
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    pub fn histograms(&self) -> &[HistogramSample] {
+        &self.histograms
+    }
+
+    /// A float series with the same labels.
+    pub fn with_samples(&self, samples: Vec<Sample>) -> Self {
+        Self {
+            metric: self.metric.clone(),
+            samples,
+            histograms: Vec::new(),
+        }
+    }
+
+    /// The same series with only the samples between `start` and `end`.
+    pub fn between(&self, start: f64, end: f64) -> Self {
+        let within = |ts: f64| (start..=end).contains(&ts);
+        Self {
+            metric: self.metric.clone(),
+            samples: self
+                .samples
+                .iter()
+                .filter(|s| within(s.timestamp))
+                .copied()
+                .collect(),
+            histograms: self
+                .histograms
+                .iter()
+                .filter(|h| within(h.timestamp))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn set_label(&mut self, name: &str, value: String) {
+        self.metric.insert(name.to_string(), value);
+    }
+}
+
+/// A float sample. The API sends values as strings so that NaN and ±Inf survive.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(from = "(f64, String)", into = "(f64, String)")]
+pub struct Sample {
+    timestamp: f64,
+    value: f64,
+}
+
+impl From<(f64, String)> for Sample {
+    fn from((timestamp, value): (f64, String)) -> Self {
+        Self::new(timestamp, value.parse().unwrap_or(f64::NAN))
+    }
+}
+
+impl From<Sample> for (f64, String) {
+    fn from(sample: Sample) -> Self {
+        (sample.timestamp, format_value(sample.value))
+    }
+}
+
+impl Sample {
+    pub fn new(timestamp: f64, value: f64) -> Self {
+        Self { timestamp, value }
+    }
+
+    pub fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Formats a value the way the API and the exposition formats spell it, e.g. `+Inf`.
+pub fn format_value(value: f64) -> String {
+    match value {
+        v if v.is_nan() => "NaN".to_string(),
+        v if v == f64::INFINITY => "+Inf".to_string(),
+        v if v == f64::NEG_INFINITY => "-Inf".to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// The envelope of every API response.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiResponse<T> {
+    status: String,
+    data: Option<T>,
+    error_type: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryData<T> {
+    result_type: String,
+    result: T,
+}
+
+/// A single labelled value returned by an instant query.
+pub struct InstantValue {
+    pub label: String,
+    pub value: f64,
+}
+
+/// Runs the range queries concurrently and returns every series paired with its label.
 pub async fn get_data(
-    addr: &str,
+    client: &Client,
+    queries: &[Query],
+    step: f64,
+    range: &TimeRange,
+) -> Result<Vec<(String, RangeSeries)>> {
+    let handles: Vec<_> = queries
+        .iter()
+        .map(|query| {
+            let client = client.clone();
+            let query = query.clone();
+            let range = range.clone();
+            tokio::spawn(async move {
+                query_range(&client, &query.expr, step, &range)
+                    .await
+                    .wrap_err_with(|| format!("query `{}` failed", query.expr))
+            })
+        })
+        .collect();
+
+    let mut result = Vec::new();
+    for (query, handle) in queries.iter().zip(handles) {
+        for v in handle.await.into_diagnostic()?? {
+            result.push((query.label(v.metric()), v));
+        }
+    }
+    if result.is_empty() {
+        return Err(miette!("empty result"));
+    }
+
+    Ok(result)
+}
+
+/// Runs a range query, reading the response itself since the client library
+/// rejects series made of native histograms.
+async fn query_range(
+    client: &Client,
     expr: &str,
     step: f64,
-    duration: u16,
-) -> Result<Vec<RangeVector>> {
-    let client = Client::try_from(addr).into_diagnostic()?;
-    let start = Timestamp::now().as_second() - (duration as i64 * 60);
-    let end = Timestamp::now().as_second();
+    range: &TimeRange,
+) -> Result<Vec<RangeSeries>> {
+    let (start, end) = range.bounds();
     let response = client
         .query_range(expr, start, end, step)
-        .get()
+        .get_raw()
         .await
         .into_diagnostic()?;
-    let result_found = response.data().as_matrix().is_some();
-    if !result_found {
-        return Err(miette!("range vector not found"));
+    let status = response.status();
+    let body = response.bytes().await.into_diagnostic()?;
+
+    let response = serde_json::from_slice(&body)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("invalid response from the server ({})", status))?;
+    range_result(response)
+}
+
+/// Reads a saved `/api/v1/query_range` response body.
+pub fn parse_range_response(body: &[u8]) -> Result<Vec<RangeSeries>> {
+    let response = serde_json::from_slice(body)
+        .into_diagnostic()
+        .wrap_err("invalid query_range response")?;
+    range_result(response)
+}
+
+fn range_result(response: ApiResponse<QueryData<Vec<RangeSeries>>>) -> Result<Vec<RangeSeries>> {
+    if response.status != "success" {
+        return Err(miette!(
+            "{}: {}",
+            response.error_type.as_deref().unwrap_or("error"),
+            response.error.as_deref().unwrap_or("unknown error")
+        ));
+    }
+    match response.data {
+        Some(data) if data.result_type == "matrix" => Ok(data.result),
+        _ => Err(miette!("range vector not found")),
+    }
+}
+
+/// Evaluates the queries concurrently at the end of `range`.
+pub async fn get_instant(
+    client: &Client,
+    queries: &[Query],
+    range: &TimeRange,
+) -> Result<Vec<InstantValue>> {
+    let handles: Vec<_> = queries
+        .iter()
+        .map(|query| {
+            let client = client.clone();
+            let query = query.clone();
+            let range = range.clone();
+            tokio::spawn(async move {
+                query_instant(&client, &query, &range)
+                    .await
+                    .wrap_err_with(|| format!("query `{}` failed", query.expr))
+            })
+        })
+        .collect();
+
+    let mut result = Vec::new();
+    for handle in handles {
+        result.extend(handle.await.into_diagnostic()??);
     }
-    let result = response.data().as_matrix().unwrap();
     if result.is_empty() {
         return Err(miette!("empty result"));
     }
 
-    Ok(result.to_vec())
+    Ok(result)
+}
+
+async fn query_instant(
+    client: &Client,
+    query: &Query,
+    range: &TimeRange,
+) -> Result<Vec<InstantValue>> {
+    let (_, end) = range.bounds();
+    let response = client
+        .query(&query.expr)
+        .at(end)
+        .get()
+        .await
+        .into_diagnostic()?;
+    match response.data() {
+        Data::Vector(vector) => Ok(vector
+            .iter()
+            .map(|v| InstantValue {
+                label: query.label(v.metric()),
+                value: v.sample().value(),
+            })
+            .collect()),
+        Data::Scalar(sample) => Ok(vec![InstantValue {
+            label: query.label(&HashMap::new()),
+            value: sample.value(),
+        }]),
+        Data::Matrix(_) => Err(miette!(
+            help = "range selectors like `metric[5m]` need a function such as rate()",
+            "instant query returned a range vector"
+        )),
+    }
+}
+
+/// Builds a `name(k=v,...)` label from a series' metric, or `{}` when it has no labels.
+pub fn series_label(metric: &HashMap<String, String>) -> String {
+    let metric_name = metric.get("__name__").cloned().unwrap_or_default();
+
+    let mut metric_labels: Vec<(&String, &String)> = metric
+        .iter()
+        .filter(|(key, value)| *key != "__name__" && !value.is_empty())
+        .collect();
+    metric_labels.sort();
+
+    let label_parts: Vec<String> = metric_labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    if label_parts.is_empty() && metric_name.is_empty() {
+        "{}".to_string()
+    } else if label_parts.is_empty() {
+        metric_name
+    } else {
+        format!("{}({})", metric_name, label_parts.join(","))
+    }
+}
+
+/// Expands `{{label}}` placeholders, leaving unknown labels empty like Grafana does.
+pub fn legend_label(legend: &str, metric: &HashMap<String, String>) -> String {
+    let mut label = String::new();
+    let mut rest = legend;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open..].find("}}") else {
+            break;
+        };
+        label.push_str(&rest[..open]);
+        let name = rest[open + 2..open + close].trim();
+        label.push_str(metric.get(name).map(String::as_str).unwrap_or_default());
+        rest = &rest[open + close + 2..];
+    }
+    label.push_str(rest);
+    label
+}
+
+/// Chart title listing every expression.
+pub fn title(queries: &[Query]) -> String {
+    queries
+        .iter()
+        .map(|q| q.expr.as_str())
+        .collect::<Vec<_>>()
+        .join(" ; ")
+}
+
+/// Sorts instant values in descending order, the way `topk` presents them.
+pub fn sort_instant(data: &mut [InstantValue]) {
+    data.sort_by(|a, b| b.value.total_cmp(&a.value));
 }
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use miette::Result;
+
+use crate::{
+    heatmap::Heatmap,
+    promql::{InstantValue, RangeSeries},
+    style::{self, ChartStyle},
+};
+
+/// Marks where a series stops, with the NaN Prometheus itself uses as its staleness
+/// marker, so that it cannot be confused with a sample that is NaN.
+pub const STALE: f64 = f64::from_bits(0x7ff0_0000_0000_0002);
+
+/// Whether `value` is the `STALE` marker rather than a sample.
+pub fn is_stale(value: f64) -> bool {
+    value.to_bits() == STALE.to_bits()
+}
+
+/// A timestamp as an exact key, for sets and maps of samples. Timestamps are whole
+/// milliseconds in the API, so they compare exactly that way.
+pub fn timestamp_key(ts: f64) -> i64 {
+    (ts * 1000.0).round() as i64
+}
+
+/// Intervals between samples longer than this many steps are missing samples, unless
+/// `--max-gap` says otherwise. Anything over one step is, on the fixed grid of a
+/// range query, but scrapes drifting against the step should not break lines.
+pub const DEFAULT_MAX_GAP: f64 = 1.5;
+
+/// Parses a `--max-gap` value.
+pub fn parse_max_gap(arg: &str) -> Result<f64, String> {
+    match arg.trim().parse::<f64>() {
+        Ok(steps) if steps >= 1.0 => Ok(steps),
+        _ => Err("the longest interval is a number of steps, at least 1, like 1.5".to_string()),
+    }
+}
+
+/// How intervals without samples are drawn.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum NullAs {
+    /// As zeros
+    Zero,
+    /// As a break in the line
+    #[default]
+    Gap,
+    /// As a straight line between the samples either side, hiding the gap
+    Connect,
+}
+
+/// When samples count as missing and how they are drawn.
+#[derive(Clone, Copy, Debug)]
+pub struct Gaps {
+    pub null_as: NullAs,
+    /// Longest interval between samples, in steps, that is not a gap
+    pub max_gap: f64,
+}
+
+impl Default for Gaps {
+    fn default() -> Self {
+        Self {
+            null_as: NullAs::default(),
+            max_gap: DEFAULT_MAX_GAP,
+        }
+    }
+}
+
+/// A series as every backend draws it, whatever it was read from.
+#[derive(Clone, Debug)]
+pub struct Series {
+    /// Shown in legends, from the legend format or the labels
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    /// Unix seconds, ascending
+    pub timestamps: Vec<f64>,
+    /// A value per timestamp, NaN for samples that are not numbers and `STALE`
+    /// where the series is interrupted
+    pub values: Vec<f64>,
+}
+
+/// Summary of a series' values, as legends and tables show it.
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub last: f64,
+}
+
+impl Series {
+    /// The float samples of a range query result. Histogram samples are left out,
+    /// they have to be turned into float series first.
+    pub fn from_range(name: &str, series: &RangeSeries) -> Self {
+        let (timestamps, values) = series
+            .samples()
+            .iter()
+            .map(|s| (s.timestamp(), s.value()))
+            .unzip();
+        Self {
+            name: name.to_string(),
+            labels: series
+                .metric()
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            timestamps,
+            values,
+        }
+    }
+
+    /// `(timestamp, value)` pairs, the form chart libraries take.
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        self.timestamps
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+            .collect()
+    }
+
+    /// Runs of samples a line connects: it breaks at `STALE` markers and at NaN and
+    /// infinite values, which have no place on the value axis.
+    pub fn segments(&self) -> Vec<Vec<(f64, f64)>> {
+        let mut segments = vec![Vec::new()];
+        for (&x, &y) in self.timestamps.iter().zip(&self.values) {
+            if y.is_finite() {
+                segments.last_mut().unwrap().push((x, y));
+            } else if segments.last().is_some_and(|s| !s.is_empty()) {
+                segments.push(Vec::new());
+            }
+        }
+        segments.retain(|s| !s.is_empty());
+        segments
+    }
+
+    /// Samples that are NaN or infinite, which charts mark on their edges.
+    pub fn invalid(&self) -> Vec<(f64, f64)> {
+        self.points()
+            .into_iter()
+            .filter(|&(_, y)| !y.is_finite() && !is_stale(y))
+            .collect()
+    }
+
+    /// The value of the sample at `ts`, if the series has one there.
+    pub fn value_at(&self, ts: f64) -> Option<f64> {
+        self.timestamps
+            .iter()
+            .position(|&x| (x - ts).abs() < 1e-3)
+            .map(|i| self.values[i])
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut sum = 0.0;
+        let mut count = 0;
+        // NaN and infinite samples would swallow every other value
+        for &y in self.values.iter().filter(|y| y.is_finite()) {
+            min = min.min(y);
+            max = max.max(y);
+            sum += y;
+            count += 1;
+        }
+        Stats {
+            min,
+            max,
+            avg: sum / count.max(1) as f64,
+            last: self
+                .values
+                .iter()
+                .rfind(|&&y| !is_stale(y))
+                .copied()
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+/// The values a chart of `series` in `style` spans: the finite values, or their
+/// running totals when stacked, with 5% to spare above and below. Filled styles
+/// include zero, which stays the bottom of the chart when nothing is negative.
+/// `null_as` is how the totals treat missing samples, see [`style::stack`].
+pub fn y_bounds<'a>(
+    series: impl IntoIterator<Item = &'a Series>,
+    style: ChartStyle,
+    null_as: NullAs,
+) -> (f64, f64) {
+    let series: Vec<&Series> = series.into_iter().collect();
+    let values: Vec<f64> = match style {
+        ChartStyle::Stacked => style::stack(&series, null_as)
+            .into_iter()
+            .flat_map(|s| s.values)
+            .collect(),
+        _ => series
+            .iter()
+            .flat_map(|s| s.values.iter().copied())
+            .collect(),
+    };
+    let (mut y_min, mut y_max) = values
+        .into_iter()
+        .filter(|y| y.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), y| {
+            (min.min(y), max.max(y))
+        });
+    // Nothing to scale to when there is no series or none has a finite sample
+    if y_min > y_max {
+        y_min = 0.0;
+        y_max = 1.0;
+    }
+    if style.is_filled() {
+        y_min = y_min.min(0.0);
+        y_max = y_max.max(0.0);
+    }
+
+    let y_padding = (y_max - y_min).abs() * 0.05;
+    if y_padding == 0.0 {
+        y_min -= 0.5;
+        y_max += 0.5;
+    } else {
+        y_min -= y_padding;
+        y_max += y_padding;
+    }
+    // Filled shapes stand on the x axis rather than floating above it
+    if style.is_filled() && y_min < 0.0 && y_min + y_padding >= 0.0 {
+        y_min = 0.0;
+    }
+    (y_min, y_max)
+}
+
+/// Where a chart spanning `y_min..y_max` marks an invalid sample: `+Inf` on the top
+/// edge, `-Inf` and NaN on the bottom one.
+pub fn edge(value: f64, y_min: f64, y_max: f64) -> f64 {
+    if value == f64::INFINITY { y_max } else { y_min }
+}
+
+/// The series one chart shows.
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub series: Vec<Series>,
+    /// The queried window in Unix seconds, which charts span even where it has no
+    /// samples, when known
+    pub range: Option<(f64, f64)>,
+    /// How missing samples were marked by [`Frame::mark_gaps`], which stacking follows
+    pub null_as: NullAs,
+}
+
+impl Frame {
+    /// Range query results, leaving out series without float samples.
+    pub fn from_range(data: &[(String, RangeSeries)]) -> Self {
+        Self {
+            series: data
+                .iter()
+                .map(|(name, v)| Series::from_range(name, v))
+                .filter(|s| !s.timestamps.is_empty())
+                .collect(),
+            range: None,
+            null_as: NullAs::default(),
+        }
+    }
+
+    /// The values a chart of every series in `style` spans, see [`y_bounds`].
+    pub fn y_bounds(&self, style: ChartStyle) -> (f64, f64) {
+        y_bounds(&self.series, style, self.null_as)
+    }
+
+    /// The step of the query, taken as the shortest interval between two samples of
+    /// a series, so that files read with `--input` need not state it.
+    fn step(&self) -> Option<f64> {
+        self.series
+            .iter()
+            .flat_map(|s| s.timestamps.windows(2).map(|pair| pair[1] - pair[0]))
+            .filter(|&interval| interval > 0.0)
+            .min_by(f64::total_cmp)
+    }
+
+    /// Finds the intervals between samples longer than `gaps.max_gap` steps and
+    /// breaks the line there with a `STALE` marker, or fills in zeros.
+    pub fn mark_gaps(&mut self, gaps: Gaps) {
+        self.null_as = gaps.null_as;
+        let Some(step) = self.step() else {
+            return;
+        };
+        if gaps.null_as == NullAs::Connect {
+            return;
+        }
+        for series in &mut self.series {
+            let mut timestamps = Vec::with_capacity(series.timestamps.len());
+            let mut values = Vec::with_capacity(series.values.len());
+            for (i, (&x, &y)) in series.timestamps.iter().zip(&series.values).enumerate() {
+                if let Some(&previous) = i.checked_sub(1).map(|i| &series.timestamps[i])
+                    && x - previous > gaps.max_gap * step
+                {
+                    match gaps.null_as {
+                        NullAs::Gap => {
+                            timestamps.push(previous + step);
+                            values.push(STALE);
+                        }
+                        NullAs::Zero => {
+                            // Half a step of slack keeps a zero off the next sample
+                            let mut missing = previous + step;
+                            while missing < x - step / 2.0 {
+                                timestamps.push(missing);
+                                values.push(0.0);
+                                missing += step;
+                            }
+                        }
+                        NullAs::Connect => {}
+                    }
+                }
+                timestamps.push(x);
+                values.push(y);
+            }
+            series.timestamps = timestamps;
+            series.values = values;
+        }
+    }
+}
+
+/// A backend drawing query results once, returning what to print.
+pub trait Renderer {
+    /// Horizontal positions a time series chart has, which sizes the automatic step.
+    fn points(&self) -> u32;
+
+    /// Horizontal cells of a heatmap, usually fewer than the points of a chart.
+    fn heatmap_points(&self) -> u32 {
+        self.points()
+    }
+
+    /// Draws the series of range queries.
+    fn render(&self, title: &str, frame: &Frame) -> Result<String>;
+
+    /// Draws the results of instant queries.
+    fn render_instant(&self, title: &str, values: Vec<InstantValue>) -> Result<String>;
+
+    /// Draws histogram buckets over time.
+    fn render_heatmap(&self, title: &str, heatmap: &Heatmap) -> Result<String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamps: &[f64]) -> Frame {
+        Frame {
+            series: vec![Series {
+                name: "a".to_string(),
+                labels: BTreeMap::new(),
+                timestamps: timestamps.to_vec(),
+                values: vec![1.0; timestamps.len()],
+            }],
+            ..Frame::default()
+        }
+    }
+
+    fn gaps(null_as: NullAs, max_gap: f64) -> Gaps {
+        Gaps { null_as, max_gap }
+    }
+
+    #[test]
+    fn mark_gaps_breaks_long_intervals() {
+        let mut frame = frame(&[0.0, 10.0, 40.0, 50.0]);
+        frame.mark_gaps(gaps(NullAs::Gap, 1.5));
+        let series = &frame.series[0];
+        assert_eq!(series.timestamps, vec![0.0, 10.0, 20.0, 40.0, 50.0]);
+        assert!(is_stale(series.values[2]));
+        assert_eq!(series.segments().len(), 2);
+    }
+
+    #[test]
+    fn mark_gaps_keeps_intervals_of_max_gap() {
+        // Exactly 1.5 steps is not a gap yet, just over it is
+        let mut frame = frame(&[0.0, 10.0, 25.0, 35.0]);
+        frame.mark_gaps(gaps(NullAs::Gap, 1.5));
+        assert_eq!(frame.series[0].timestamps.len(), 4);
+
+        let mut frame = self::frame(&[0.0, 10.0, 25.5, 35.5]);
+        frame.mark_gaps(gaps(NullAs::Gap, 1.5));
+        assert_eq!(frame.series[0].timestamps.len(), 5);
+    }
+
+    #[test]
+    fn mark_gaps_fills_zeros() {
+        let mut frame = frame(&[0.0, 10.0, 40.0]);
+        frame.mark_gaps(gaps(NullAs::Zero, 1.5));
+        let series = &frame.series[0];
+        assert_eq!(series.timestamps, vec![0.0, 10.0, 20.0, 30.0, 40.0]);
+        assert_eq!(series.values, vec![1.0, 1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn mark_gaps_zero_keeps_half_a_step_off_the_next_sample() {
+        // A missing sample 20 would sit within half a step of the one at 24
+        let mut frame = frame(&[0.0, 10.0, 24.0]);
+        frame.mark_gaps(gaps(NullAs::Zero, 1.0));
+        assert_eq!(frame.series[0].timestamps, vec![0.0, 10.0, 24.0]);
+
+        let mut frame = self::frame(&[0.0, 10.0, 26.0]);
+        frame.mark_gaps(gaps(NullAs::Zero, 1.0));
+        assert_eq!(frame.series[0].timestamps, vec![0.0, 10.0, 20.0, 26.0]);
+    }
+
+    #[test]
+    fn mark_gaps_connect_leaves_series() {
+        let mut frame = frame(&[0.0, 10.0, 40.0]);
+        frame.mark_gaps(gaps(NullAs::Connect, 1.5));
+        assert_eq!(frame.series[0].timestamps, vec![0.0, 10.0, 40.0]);
+    }
+
+    #[test]
+    fn mark_gaps_without_step() {
+        let mut frame = frame(&[10.0]);
+        frame.mark_gaps(gaps(NullAs::Gap, 1.5));
+        assert_eq!(frame.series[0].timestamps, vec![10.0]);
+    }
+
+    #[test]
+    fn segments_break_at_invalid_values() {
+        let series = Series {
+            name: "a".to_string(),
+            labels: BTreeMap::new(),
+            timestamps: vec![0.0, 1.0, 2.0, 3.0, 4.0],
+            values: vec![1.0, f64::NAN, 2.0, STALE, f64::INFINITY],
+        };
+        assert_eq!(series.segments(), vec![vec![(0.0, 1.0)], vec![(2.0, 2.0)]]);
+        assert_eq!(series.invalid().len(), 2);
+        assert_eq!(series.stats().last, f64::INFINITY);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::series::{NullAs, STALE, Series, timestamp_key};
+
+/// How the series of a time series chart are drawn.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartStyle {
+    /// Overlaid lines
+    #[default]
+    Line,
+    /// Lines filled down to zero
+    Area,
+    /// Filled areas summed on top of each other
+    Stacked,
+    /// A bar per sample
+    Bars,
+    /// The samples alone, unconnected
+    Points,
+    /// Lines holding each value until the next sample
+    Steps,
+}
+
+impl ChartStyle {
+    /// Whether the style fills down to zero, so the value axis has to include it.
+    pub fn is_filled(&self) -> bool {
+        matches!(
+            self,
+            ChartStyle::Area | ChartStyle::Stacked | ChartStyle::Bars
+        )
+    }
+}
+
+/// Sums series on top of each other, each one becoming the running total of itself
+/// and the series before it, aligned on the union of all timestamps. A total is
+/// only known where every series up to it has a value, so a `STALE` marker, NaN or
+/// infinite value below breaks it like [`Series::segments`] breaks a line. Where a
+/// series has no sample, `null_as` decides: zero adds nothing, gap breaks the
+/// total, connect leaves the timestamp out so the line runs across.
+pub fn stack(series: &[&Series], null_as: NullAs) -> Vec<Series> {
+    let timestamps: Vec<f64> = series
+        .iter()
+        .flat_map(|s| s.timestamps.iter())
+        .map(|&ts| (timestamp_key(ts), ts))
+        .collect::<BTreeMap<_, _>>()
+        .into_values()
+        .collect();
+
+    let mut totals: Vec<Option<f64>> = vec![Some(0.0); timestamps.len()];
+    series
+        .iter()
+        .map(|s| {
+            for (total, value) in totals.iter_mut().zip(aligned(s, &timestamps, null_as)) {
+                // Kept as they are, so `STALE` stays distinct from NaN
+                *total = total.zip(value).map(|(total, value)| match (total, value) {
+                    (total, _) if !total.is_finite() => total,
+                    (_, value) if !value.is_finite() => value,
+                    (total, value) => total + value,
+                });
+            }
+            let (timestamps, values) = timestamps
+                .iter()
+                .zip(&totals)
+                .filter_map(|(&ts, total)| Some((ts, (*total)?)))
+                .unzip();
+            Series {
+                timestamps,
+                values,
+                ..(*s).clone()
+            }
+        })
+        .collect()
+}
+
+/// The values of `series` at `timestamps`, both ascending: its samples, and between
+/// two finite ones the line joining them. Anywhere else it has no value, which is
+/// zero, `STALE` or left out as `null_as` says.
+fn aligned(series: &Series, timestamps: &[f64], null_as: NullAs) -> Vec<Option<f64>> {
+    let samples: Vec<(f64, f64)> = series.points();
+    let mut next = 0;
+    timestamps
+        .iter()
+        .map(|&ts| {
+            while samples
+                .get(next)
+                .is_some_and(|&(x, _)| timestamp_key(x) < timestamp_key(ts))
+            {
+                next += 1;
+            }
+            match (next.checked_sub(1).map(|i| samples[i]), samples.get(next)) {
+                (_, Some(&(x, y))) if timestamp_key(x) == timestamp_key(ts) => return Some(y),
+                (Some((x0, y0)), Some(&(x1, y1))) if y0.is_finite() && y1.is_finite() => {
+                    return Some(y0 + (y1 - y0) * (ts - x0) / (x1 - x0));
+                }
+                _ => {}
+            }
+            match null_as {
+                NullAs::Zero => Some(0.0),
+                NullAs::Gap => Some(STALE),
+                NullAs::Connect => None,
+            }
+        })
+        .collect()
+}
+
+/// Inserts a point before every sample so that lines through the result hold each
+/// value until the next sample.
+pub fn steps<X: Copy>(points: &[(X, f64)]) -> Vec<(X, f64)> {
+    let mut result = Vec::with_capacity(points.len() * 2);
+    for pair in points.windows(2) {
+        result.push(pair[0]);
+        result.push((pair[1].0, pair[0].1));
+    }
+    result.extend(points.last());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::series::is_stale;
+
+    fn series(points: &[(f64, f64)]) -> Series {
+        Series {
+            name: String::new(),
+            labels: BTreeMap::new(),
+            timestamps: points.iter().map(|&(x, _)| x).collect(),
+            values: points.iter().map(|&(_, y)| y).collect(),
+        }
+    }
+
+    fn totals(stacked: &Series) -> Vec<(f64, f64)> {
+        stacked.points()
+    }
+
+    #[test]
+    fn stack_sums_running_totals() {
+        let a = series(&[(0.0, 1.0), (10.0, 2.0)]);
+        let b = series(&[(0.0, 3.0), (10.0, 4.0)]);
+        let stacked = stack(&[&a, &b], NullAs::Gap);
+        assert_eq!(totals(&stacked[0]), vec![(0.0, 1.0), (10.0, 2.0)]);
+        assert_eq!(totals(&stacked[1]), vec![(0.0, 4.0), (10.0, 6.0)]);
+    }
+
+    #[test]
+    fn stack_interpolates_between_samples() {
+        let a = series(&[(0.0, 1.0), (10.0, 3.0)]);
+        let b = series(&[(5.0, 1.0)]);
+        let stacked = stack(&[&a, &b], NullAs::Gap);
+        assert_eq!(
+            totals(&stacked[0]),
+            vec![(0.0, 1.0), (5.0, 2.0), (10.0, 3.0)]
+        );
+        assert_eq!(stacked[1].values[1], 3.0);
+    }
+
+    #[test]
+    fn stack_breaks_at_gaps() {
+        let a = series(&[(0.0, 1.0), (10.0, STALE), (20.0, 1.0)]);
+        let b = series(&[(0.0, 1.0), (10.0, 1.0), (20.0, 1.0)]);
+        let stacked = stack(&[&a, &b], NullAs::Gap);
+        assert!(is_stale(stacked[0].values[1]));
+        // The total above a gap is unknown too, rather than dropping to b alone
+        assert!(is_stale(stacked[1].values[1]));
+        assert_eq!(
+            stacked[1].segments(),
+            vec![vec![(0.0, 2.0)], vec![(20.0, 2.0)]]
+        );
+    }
+
+    #[test]
+    fn stack_breaks_at_invalid_values() {
+        let a = series(&[(0.0, 1.0), (10.0, f64::NAN), (20.0, 1.0)]);
+        let b = series(&[(0.0, 1.0), (10.0, f64::INFINITY), (20.0, 1.0)]);
+        for null_as in [NullAs::Gap, NullAs::Zero, NullAs::Connect] {
+            let stacked = stack(&[&a, &b], null_as);
+            assert_eq!(stacked[0].segments().len(), 2);
+            assert_eq!(stacked[1].segments().len(), 2);
+        }
+    }
+
+    #[test]
+    fn stack_missing_samples() {
+        // b starts late, so it has no value at 0
+        let a = series(&[(0.0, 1.0), (10.0, 1.0), (20.0, 1.0)]);
+        let b = series(&[(10.0, 2.0), (20.0, 2.0)]);
+
+        let stacked = stack(&[&a, &b], NullAs::Gap);
+        assert!(is_stale(stacked[1].values[0]));
+        assert_eq!(stacked[1].segments(), vec![vec![(10.0, 3.0), (20.0, 3.0)]]);
+
+        let stacked = stack(&[&a, &b], NullAs::Zero);
+        assert_eq!(
+            totals(&stacked[1]),
+            vec![(0.0, 1.0), (10.0, 3.0), (20.0, 3.0)]
+        );
+
+        let stacked = stack(&[&b, &a], NullAs::Connect);
+        assert_eq!(totals(&stacked[0]), vec![(10.0, 2.0), (20.0, 2.0)]);
+        assert_eq!(totals(&stacked[1]), vec![(10.0, 3.0), (20.0, 3.0)]);
+    }
+
+    #[test]
+    fn steps_hold_values() {
+        assert_eq!(
+            steps(&[(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)]),
+            vec![(0.0, 1.0), (1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 3.0)]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use clap::ValueEnum;
+
+/// Columns and rows assumed when neither the terminal nor the environment tell.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// The size of the terminal in columns and rows. Without one, like under cron or
+/// in CI, `$COLUMNS` and `$LINES` are used as shells set them, else 80x24.
+pub fn size() -> (u16, u16) {
+    if let Ok((columns, rows)) = crossterm::terminal::size()
+        && columns > 0
+        && rows > 0
+    {
+        return (columns, rows);
+    }
+    let var = |name| {
+        env::var(name)
+            .ok()
+            .and_then(|value| value.trim().parse::<u16>().ok())
+            .filter(|&value| value > 0)
+    };
+    (
+        var("COLUMNS").unwrap_or(DEFAULT_SIZE.0),
+        var("LINES").unwrap_or(DEFAULT_SIZE.1),
+    )
+}
+
+/// `text` cut to `width` characters, ending with an ellipsis where it was cut.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// Whether output is coloured with ANSI escape codes.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
@@ -0,0 +1,643 @@
+use jiff::{
+    Span, SpanTotal, Timestamp, ToSpan, Unit,
+    civil::{DateTime, Time},
+    tz::TimeZone,
+};
+use miette::{LabeledSpan, Report, Result, miette};
+
+const TIME_HELP: &str = "use RFC 3339 (2024-05-01T14:00:00Z), Unix seconds (1714572000), \
+    a local date and time (2024-05-01 14:00), now, now-3h, today 09:00 or yesterday 14:00";
+
+const STEP_HELP: &str = "use auto, a number of seconds (15, 0.5) or a duration like 30s, 5m, 1h";
+
+/// Narrowest window zooming in can reach, in seconds.
+const MIN_ZOOM_RANGE: i64 = 10;
+
+/// Prometheus rejects range queries returning more than this many points per series.
+const MAX_POINTS_PER_SERIES: f64 = 11_000.0;
+
+/// Readable steps in seconds, each paired with the raw interval it is used below,
+/// after Grafana's `roundInterval`.
+const NICE_STEPS: &[(f64, f64)] = &[
+    (1.5, 1.0),
+    (3.5, 2.0),
+    (7.5, 5.0),
+    (12.5, 10.0),
+    (17.5, 15.0),
+    (25.0, 20.0),
+    (45.0, 30.0),
+    (90.0, 60.0),
+    (210.0, 120.0),
+    (450.0, 300.0),
+    (750.0, 600.0),
+    (1050.0, 900.0),
+    (1500.0, 1200.0),
+    (2700.0, 1800.0),
+    (5400.0, 3600.0),
+    (9000.0, 7200.0),
+    (16200.0, 10800.0),
+    (24300.0, 21600.0),
+    (64800.0, 43200.0),
+    (129600.0, 86400.0),
+    (604800.0, 86400.0),
+    (1814400.0, 604800.0),
+    (3628800.0, 2592000.0),
+];
+
+const INTERVAL_HELP: &str = "use a number of seconds (30) or a duration like 30s, 5m, 1h";
+
+const DURATION_HELP: &str = "use a duration like 90s, 15m, 6h, 7d or 1h30m; \
+    bare numbers are minutes";
+
+/// A point in time given on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeSpec {
+    /// Seconds relative to the moment the query runs, so live views keep moving.
+    Now(i64),
+    Absolute(i64),
+}
+
+impl TimeSpec {
+    fn resolve(&self, now: i64) -> i64 {
+        match *self {
+            TimeSpec::Now(offset) => now + offset,
+            TimeSpec::Absolute(ts) => ts,
+        }
+    }
+}
+
+/// The window a range query covers, built from `--start`, `--end` and `--duration`.
+#[derive(Clone, Debug)]
+pub struct TimeRange {
+    start: Option<TimeSpec>,
+    end: Option<TimeSpec>,
+    duration: i64,
+}
+
+impl TimeRange {
+    pub fn from_args(start: Option<&str>, end: Option<&str>, duration: &str) -> Result<Self> {
+        let now = Timestamp::now();
+        let range = Self {
+            start: start
+                .map(|value| parse_time("--start", value, &now))
+                .transpose()?,
+            end: end
+                .map(|value| parse_time("--end", value, &now))
+                .transpose()?,
+            duration: parse_duration("--duration", duration)?,
+        };
+
+        let (start_ts, end_ts) = range.bounds();
+        // Clamped by `bounds_at`, for the window would start before any time there is
+        if range.start.is_none() && end_ts - start_ts < range.duration {
+            return Err(bad_argument(
+                "--duration",
+                duration,
+                "out of range",
+                DURATION_HELP,
+                format!("invalid duration `{}`", duration),
+            ));
+        }
+        if let Some(start) = start
+            && start_ts >= end_ts
+        {
+            let start_offset = "--start ".len();
+            let mut labels = vec![LabeledSpan::at(
+                start_offset..start_offset + start.len(),
+                "start",
+            )];
+            let source = match end {
+                Some(end) => {
+                    let end_offset = start_offset + start.len() + " --end ".len();
+                    labels.push(LabeledSpan::at(end_offset..end_offset + end.len(), "end"));
+                    format!("--start {} --end {}", start, end)
+                }
+                None => format!("--start {}", start),
+            };
+            return Err(miette!(
+                labels = labels,
+                help = "the start of the range must be before its end, which defaults to now",
+                "empty time range"
+            )
+            .with_source_code(source));
+        }
+
+        Ok(range)
+    }
+
+    /// The last `duration` seconds, moving with the clock.
+    pub fn live(duration: i64) -> Self {
+        Self {
+            start: None,
+            end: None,
+            duration,
+        }
+    }
+
+    /// A window that stays put, as left by zooming or panning.
+    pub fn fixed(start: i64, end: i64) -> Self {
+        Self {
+            start: Some(TimeSpec::Absolute(start)),
+            end: Some(TimeSpec::Absolute(end)),
+            duration: end - start,
+        }
+    }
+
+    /// Whether the window ends now and follows the clock, so refreshing shows new
+    /// samples. Windows ending some time before now, like `--end now-2h`, are not.
+    pub fn is_live(&self) -> bool {
+        matches!(self.end, None | Some(TimeSpec::Now(0)))
+            && !matches!(self.start, Some(TimeSpec::Absolute(_)))
+    }
+
+    /// Whether the window follows the clock at all, live or some time before now.
+    pub fn is_relative(&self) -> bool {
+        !matches!(self.start, Some(TimeSpec::Absolute(_)))
+            && !matches!(self.end, Some(TimeSpec::Absolute(_)))
+    }
+
+    /// Scales the window by `factor` around its center. Live windows keep their end
+    /// at now and stay live, others are left fixed.
+    pub fn zoom(&self, factor: f64) -> Self {
+        let (start, end) = self.bounds();
+        let duration = (((end - start) as f64 * factor).round() as i64).max(MIN_ZOOM_RANGE);
+        if self.is_live() {
+            return Self::live(duration);
+        }
+        let center = (start + end) / 2;
+        Self::fixed(center - duration / 2, center - duration / 2 + duration)
+    }
+
+    /// Shifts the window by `fraction` of its width, later for positive fractions.
+    pub fn pan(&self, fraction: f64) -> Self {
+        let (start, end) = self.bounds();
+        let shift = ((end - start) as f64 * fraction).round() as i64;
+        Self::fixed(start + shift, end + shift)
+    }
+
+    /// Resolves the range to `(start, end)` Unix seconds as of now.
+    pub fn bounds(&self) -> (i64, i64) {
+        self.bounds_at(Timestamp::now().as_second())
+    }
+
+    /// Resolves the range as of `now`. `--duration` only counts without `--start`,
+    /// a start alone runs to now. Durations reaching before the earliest time are cut
+    /// there, so zooming out never overflows.
+    fn bounds_at(&self, now: i64) -> (i64, i64) {
+        let earliest = Timestamp::MIN.as_second();
+        let before = |end: i64| {
+            end.checked_sub(self.duration)
+                .map_or(earliest, |start| start.max(earliest))
+        };
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => (start.resolve(now), end.resolve(now)),
+            (Some(start), None) => (start.resolve(now), now),
+            (None, Some(end)) => {
+                let end = end.resolve(now);
+                (before(end), end)
+            }
+            (None, None) => (before(now), now),
+        }
+    }
+}
+
+/// Query resolution, either fixed or derived from the range and the plot width.
+#[derive(Clone, Copy, Debug)]
+pub enum Step {
+    Auto,
+    Fixed(f64),
+}
+
+impl Step {
+    pub fn from_arg(value: &str) -> Result<Self> {
+        if value == "auto" {
+            return Ok(Step::Auto);
+        }
+
+        let seconds = match value.parse::<f64>() {
+            // inf and nan parse as numbers too
+            Ok(seconds) if seconds.is_finite() => Some(seconds),
+            Ok(_) => None,
+            Err(_) => span_seconds(value).map(|seconds| seconds as f64),
+        };
+        match seconds {
+            // No longer than the span from 1970 to the latest time there is
+            Some(seconds) if seconds > Timestamp::MAX.as_second() as f64 => Err(bad_argument(
+                "--step",
+                value,
+                "out of range",
+                STEP_HELP,
+                format!("invalid step `{}`", value),
+            )),
+            Some(seconds) if seconds > 0.0 => Ok(Step::Fixed(seconds)),
+            Some(_) => Err(bad_argument(
+                "--step",
+                value,
+                "must be positive",
+                STEP_HELP,
+                format!("invalid step `{}`", value),
+            )),
+            None => Err(bad_argument(
+                "--step",
+                value,
+                "not a step",
+                STEP_HELP,
+                format!("invalid step `{}`", value),
+            )),
+        }
+    }
+
+    /// Resolves the step in seconds for `range` drawn across `points` horizontal positions.
+    pub fn seconds(&self, range: &TimeRange, points: u32) -> f64 {
+        match *self {
+            Step::Fixed(seconds) => seconds,
+            Step::Auto => {
+                let (start, end) = range.bounds();
+                auto_step((end - start) as f64, points)
+            }
+        }
+    }
+}
+
+/// Picks a readable step giving roughly one point per horizontal position.
+fn auto_step(range: f64, points: u32) -> f64 {
+    let min_step = range / MAX_POINTS_PER_SERIES;
+    let raw = (range / points.max(1) as f64).max(min_step);
+
+    let step = NICE_STEPS
+        .iter()
+        .find(|&&(threshold, _)| raw < threshold)
+        .map(|&(_, step)| step)
+        .unwrap_or(31_536_000.0);
+
+    // Rounding down must not push the query over the server's point limit
+    if step < min_step {
+        min_step.ceil()
+    } else {
+        step
+    }
+}
+
+fn bad_argument(arg: &str, value: &str, label: &str, help: &str, message: String) -> Report {
+    let offset = arg.len() + 1;
+    miette!(
+        labels = vec![LabeledSpan::at(offset..offset + value.len(), label)],
+        help = help.to_string(),
+        "{}",
+        message
+    )
+    .with_source_code(format!("{} {}", arg, value))
+}
+
+/// Parses a Prometheus-style duration into seconds.
+pub fn parse_duration(arg: &str, value: &str) -> Result<i64> {
+    let invalid = || {
+        bad_argument(
+            arg,
+            value,
+            "not a duration",
+            DURATION_HELP,
+            format!("invalid duration `{}`", value),
+        )
+    };
+    let seconds = if let Ok(minutes) = value.parse::<i64>() {
+        minutes.checked_mul(60).ok_or_else(invalid)?
+    } else {
+        span_seconds(value).ok_or_else(invalid)?
+    };
+
+    if seconds <= 0 {
+        return Err(bad_argument(
+            arg,
+            value,
+            "must be positive",
+            DURATION_HELP,
+            format!("invalid duration `{}`", value),
+        ));
+    }
+
+    Ok(seconds)
+}
+
+/// Parses a refresh interval into seconds. Bare numbers are seconds, as for
+/// `--refresh`, not minutes as for `--duration`.
+pub fn parse_interval(arg: &str, value: &str) -> Result<u64> {
+    let seconds = match value.trim().parse::<u64>() {
+        Ok(seconds) => Some(seconds),
+        Err(_) => span_seconds(value).and_then(|seconds| u64::try_from(seconds).ok()),
+    };
+    match seconds {
+        Some(seconds) if seconds > 0 => Ok(seconds),
+        Some(_) => Err(bad_argument(
+            arg,
+            value,
+            "must be positive",
+            INTERVAL_HELP,
+            format!("invalid refresh interval `{}`", value),
+        )),
+        None => Err(bad_argument(
+            arg,
+            value,
+            "not an interval",
+            INTERVAL_HELP,
+            format!("invalid refresh interval `{}`", value),
+        )),
+    }
+}
+
+fn span_seconds(value: &str) -> Option<i64> {
+    let span: Span = value.trim().parse().ok()?;
+    let total = span
+        .total(SpanTotal::from(Unit::Second).days_are_24_hours())
+        .ok()?;
+    Some(total.round() as i64)
+}
+
+fn parse_time(arg: &str, value: &str, now: &Timestamp) -> Result<TimeSpec> {
+    let trimmed = value.trim();
+    let invalid = || {
+        bad_argument(
+            arg,
+            value,
+            "not a time",
+            TIME_HELP,
+            format!("invalid time `{}`", value),
+        )
+    };
+
+    if trimmed == "now" {
+        return Ok(TimeSpec::Now(0));
+    }
+
+    if let Some(offset) = trimmed.strip_prefix("now") {
+        let offset = offset.trim();
+        let seconds = if let Some(span) = offset.strip_prefix('-') {
+            -span_seconds(span).ok_or_else(invalid)?
+        } else if let Some(span) = offset.strip_prefix('+') {
+            span_seconds(span).ok_or_else(invalid)?
+        } else {
+            return Err(invalid());
+        };
+        return Ok(TimeSpec::Now(seconds));
+    }
+
+    if let Ok(seconds) = trimmed.parse::<f64>() {
+        // The cast saturates inf and 1e300, which are then no valid timestamp
+        if seconds.is_nan() || Timestamp::from_second(seconds as i64).is_err() {
+            return Err(bad_argument(
+                arg,
+                value,
+                "out of range",
+                TIME_HELP,
+                format!("invalid time `{}`", value),
+            ));
+        }
+        return Ok(TimeSpec::Absolute(seconds as i64));
+    }
+
+    if let Ok(ts) = trimmed.parse::<Timestamp>() {
+        return Ok(TimeSpec::Absolute(ts.as_second()));
+    }
+
+    let today = now.to_zoned(TimeZone::system()).date();
+    let day_and_time = if let Some(time) = trimmed.strip_prefix("yesterday") {
+        Some((today.checked_sub(1.day()).map_err(|_| invalid())?, time))
+    } else {
+        trimmed.strip_prefix("today").map(|time| (today, time))
+    };
+
+    let datetime = if let Some((day, time)) = day_and_time {
+        let time = time.trim();
+        let time = if time.is_empty() {
+            Time::midnight()
+        } else {
+            time.parse::<Time>().map_err(|_| invalid())?
+        };
+        day.to_datetime(time)
+    } else {
+        trimmed.parse::<DateTime>().map_err(|_| invalid())?
+    };
+
+    let zoned = datetime
+        .to_zoned(TimeZone::system())
+        .map_err(|_| invalid())?;
+    Ok(TimeSpec::Absolute(zoned.timestamp().as_second()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> Timestamp {
+        "2024-05-01T14:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn parse_time_relative() {
+        assert_eq!(
+            parse_time("--start", "now", &now()).unwrap(),
+            TimeSpec::Now(0)
+        );
+        assert_eq!(
+            parse_time("--start", "now-3h", &now()).unwrap(),
+            TimeSpec::Now(-3 * 3600)
+        );
+        assert_eq!(
+            parse_time("--start", "now + 90s", &now()).unwrap(),
+            TimeSpec::Now(90)
+        );
+    }
+
+    #[test]
+    fn parse_time_absolute() {
+        assert_eq!(
+            parse_time("--start", "1714572000", &now()).unwrap(),
+            TimeSpec::Absolute(1714572000)
+        );
+        assert_eq!(
+            parse_time("--start", "2024-05-01T14:00:00Z", &now()).unwrap(),
+            TimeSpec::Absolute(1714572000)
+        );
+        let Ok(TimeSpec::Absolute(today)) = parse_time("--start", "today 09:00", &now()) else {
+            panic!("today 09:00 is not an absolute time");
+        };
+        let Ok(TimeSpec::Absolute(yesterday)) = parse_time("--start", "yesterday 09:00", &now())
+        else {
+            panic!("yesterday 09:00 is not an absolute time");
+        };
+        // Daylight saving time may shift the day by an hour
+        assert!((today - yesterday - 86400).abs() <= 3600);
+    }
+
+    #[test]
+    fn parse_time_invalid() {
+        for value in ["", "soon", "now3h", "now-", "yesterday 25:00", "2024-13-01"] {
+            assert!(parse_time("--start", value, &now()).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parse_time_out_of_range() {
+        for value in [
+            "inf",
+            "-inf",
+            "nan",
+            "1e300",
+            "-1e300",
+            "9223372036854775807",
+        ] {
+            assert!(parse_time("--end", value, &now()).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn step_from_arg() {
+        assert!(matches!(Step::from_arg("auto"), Ok(Step::Auto)));
+        assert!(matches!(Step::from_arg("0.5"), Ok(Step::Fixed(0.5))));
+        assert!(matches!(Step::from_arg("5m"), Ok(Step::Fixed(300.0))));
+        for value in ["0", "-15", "inf", "nan", "1e300", "soon"] {
+            assert!(Step::from_arg(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("--duration", "90s").unwrap(), 90);
+        assert_eq!(parse_duration("--duration", "15m").unwrap(), 900);
+        assert_eq!(parse_duration("--duration", "1h30m").unwrap(), 5400);
+        assert_eq!(parse_duration("--duration", "7d").unwrap(), 7 * 86400);
+    }
+
+    #[test]
+    fn parse_duration_bare_numbers_are_minutes() {
+        assert_eq!(parse_duration("--duration", "5").unwrap(), 300);
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        for value in ["", "0", "-5", "0s", "abc", "999999999999999999"] {
+            assert!(parse_duration("--duration", value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parse_interval_bare_numbers_are_seconds() {
+        assert_eq!(parse_interval("refresh", "30").unwrap(), 30);
+        assert_eq!(parse_interval("refresh", "30s").unwrap(), 30);
+        assert_eq!(parse_interval("refresh", "5m").unwrap(), 300);
+    }
+
+    #[test]
+    fn parse_interval_invalid() {
+        for value in ["", "0", "-5", "0s", "-1m", "soon"] {
+            assert!(parse_interval("refresh", value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn from_args_duration_ends_now() {
+        let range = TimeRange::from_args(None, None, "1h").unwrap();
+        let now = Timestamp::now().as_second();
+        assert_eq!(range.bounds_at(now), (now - 3600, now));
+        assert!(range.is_live());
+    }
+
+    #[test]
+    fn from_args_start_runs_to_now() {
+        let range = TimeRange::from_args(Some("now-3h"), None, "1m").unwrap();
+        let now = Timestamp::now().as_second();
+        assert_eq!(range.bounds_at(now), (now - 3 * 3600, now));
+    }
+
+    #[test]
+    fn from_args_end_with_duration() {
+        let range = TimeRange::from_args(None, Some("1714572000"), "2h").unwrap();
+        assert_eq!(range.bounds(), (1714572000 - 7200, 1714572000));
+    }
+
+    #[test]
+    fn from_args_start_and_end() {
+        let range = TimeRange::from_args(Some("1714572000"), Some("1714575600"), "1m").unwrap();
+        assert_eq!(range.bounds(), (1714572000, 1714575600));
+    }
+
+    #[test]
+    fn from_args_empty_range() {
+        assert!(TimeRange::from_args(Some("1714575600"), Some("1714572000"), "1m").is_err());
+        assert!(TimeRange::from_args(Some("now"), Some("now"), "1m").is_err());
+        assert!(TimeRange::from_args(Some("now+1h"), None, "1m").is_err());
+    }
+
+    #[test]
+    fn from_args_invalid() {
+        assert!(TimeRange::from_args(Some("soon"), None, "1m").is_err());
+        assert!(TimeRange::from_args(None, Some("soon"), "1m").is_err());
+        assert!(TimeRange::from_args(None, None, "forever").is_err());
+        assert!(TimeRange::from_args(None, Some("inf"), "1m").is_err());
+        assert!(TimeRange::from_args(None, None, "999999999999999").is_err());
+    }
+
+    #[test]
+    fn zoom_out_stops_at_earliest_time() {
+        let mut range = TimeRange::live(3600);
+        for _ in 0..100 {
+            range = range.zoom(2.0);
+        }
+        let (start, end) = range.bounds();
+        assert_eq!(start, Timestamp::MIN.as_second());
+        assert!(end > start);
+    }
+
+    #[test]
+    fn is_live_only_when_ending_now() {
+        let range = |start, end| TimeRange::from_args(start, end, "1h").unwrap();
+        assert!(range(None, None).is_live());
+        assert!(range(Some("now-3h"), None).is_live());
+        assert!(range(Some("now-3h"), Some("now")).is_live());
+        assert!(!range(Some("now-3h"), Some("now-2h")).is_live());
+        assert!(!range(None, Some("now-2h")).is_live());
+        assert!(!range(Some("1714572000"), None).is_live());
+        assert!(range(Some("now-3h"), Some("now-2h")).is_relative());
+    }
+
+    #[test]
+    fn zoom_fixed_keeps_center() {
+        let range = TimeRange::fixed(1000, 2000);
+        assert_eq!(range.zoom(0.5).bounds(), (1250, 1750));
+        assert_eq!(range.zoom(2.0).bounds(), (500, 2500));
+        assert!(!range.zoom(2.0).is_live());
+    }
+
+    #[test]
+    fn zoom_in_stops_at_minimum() {
+        let range = TimeRange::fixed(1000, 1012);
+        assert_eq!(range.zoom(0.1).bounds(), (1001, 1011));
+    }
+
+    #[test]
+    fn zoom_live_stays_live() {
+        let zoomed = TimeRange::live(3600).zoom(0.5);
+        assert!(zoomed.is_live());
+        let now = Timestamp::now().as_second();
+        assert_eq!(zoomed.bounds_at(now), (now - 1800, now));
+    }
+
+    #[test]
+    fn zoom_before_now_keeps_center() {
+        let range = TimeRange::from_args(Some("now-3h"), Some("now-2h"), "1m").unwrap();
+        let (start, end) = range.bounds();
+        let zoomed = range.zoom(0.5);
+        assert!(!zoomed.is_live());
+        let (zoomed_start, zoomed_end) = zoomed.bounds();
+        assert_eq!(zoomed_end - zoomed_start, 1800);
+        assert!(((zoomed_start + zoomed_end) / 2 - (start + end) / 2).abs() <= 1);
+    }
+
+    #[test]
+    fn pan_shifts_by_fraction() {
+        let range = TimeRange::fixed(1000, 2000);
+        assert_eq!(range.pan(0.25).bounds(), (1250, 2250));
+        assert_eq!(range.pan(-0.5).bounds(), (500, 1500));
+        assert!(!TimeRange::live(3600).pan(-0.25).is_live());
+    }
+}
@@ -0,0 +1,277 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::promql::{Query, RangeSeries};
+
+const SI_PREFIXES: &[&str] = &["", "k", "M", "G", "T", "P", "E"];
+const IEC_PREFIXES: &[&str] = &["", "Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+
+/// Units of durations in seconds, ascending.
+const DURATION_UNITS: &[(f64, &str)] = &[
+    (1e-9, "ns"),
+    (1e-6, "µs"),
+    (1e-3, "ms"),
+    (1.0, "s"),
+    (60.0, "min"),
+    (3600.0, "h"),
+    (86400.0, "d"),
+];
+
+/// How values are scaled and suffixed for display. Names follow Grafana's unit ids
+/// where there is one, so dashboard units carry over.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    /// Plain numbers with two decimals
+    #[default]
+    None,
+    /// Numbers with k, M, G, ... suffixes
+    Short,
+    /// Bytes with IEC prefixes (KiB, MiB, ...)
+    #[value(alias = "bytes-iec")]
+    Bytes,
+    /// Bytes with SI prefixes (kB, MB, ...)
+    #[value(alias = "bytes-si")]
+    Decbytes,
+    /// Bytes per second with IEC prefixes (KiB/s, MiB/s, ...)
+    #[value(alias = "bytes-per-second")]
+    Binbps,
+    /// Bits per second with SI prefixes
+    #[value(alias = "bits")]
+    Bps,
+    #[value(alias = "s")]
+    Seconds,
+    #[value(alias = "ms")]
+    Milliseconds,
+    /// Values already in percent (0-100)
+    Percent,
+    /// Ratios shown as percent (0.0-1.0)
+    Percentunit,
+    /// Operations per second
+    #[value(alias = "reqps")]
+    Ops,
+}
+
+impl Unit {
+    /// Looks up a unit by name, as given to `--unit` or in a dashboard.
+    pub fn from_name(name: &str) -> Option<Self> {
+        <Self as ValueEnum>::from_str(name, true).ok()
+    }
+
+    /// Guesses the unit from the metric names in the expressions, following the
+    /// Prometheus naming conventions. Expressions disagreeing give no unit.
+    pub fn infer(queries: &[Query]) -> Self {
+        agreed(queries.iter().map(|q| infer_expr(&q.expr)))
+    }
+
+    /// Guesses the unit from the metric names of series read without their
+    /// expressions, like `--input` files.
+    pub fn infer_series(data: &[(String, RangeSeries)]) -> Self {
+        agreed(
+            data.iter()
+                .map(|(_, v)| infer_expr(v.metric().get("__name__").map_or("", String::as_str))),
+        )
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        match self {
+            Unit::None => format!("{:.2}", value),
+            Unit::Short => scaled(value, 1000.0, SI_PREFIXES, ""),
+            Unit::Bytes => scaled(value, 1024.0, IEC_PREFIXES, "B"),
+            Unit::Decbytes => scaled(value, 1000.0, SI_PREFIXES, "B"),
+            Unit::Binbps => scaled(value, 1024.0, IEC_PREFIXES, "B/s"),
+            Unit::Bps => scaled(value, 1000.0, SI_PREFIXES, "b/s"),
+            Unit::Seconds => duration(value),
+            Unit::Milliseconds => duration(value / 1000.0),
+            Unit::Percent => format!("{:.2}%", value),
+            Unit::Percentunit => format!("{:.2}%", value * 100.0),
+            Unit::Ops => format!("{} ops/s", scaled(value, 1000.0, SI_PREFIXES, "")),
+        }
+    }
+}
+
+/// The unit all guesses agree on, if there is one.
+fn agreed(mut units: impl Iterator<Item = Unit>) -> Unit {
+    let Some(first) = units.next() else {
+        return Unit::None;
+    };
+    if units.all(|unit| unit == first) {
+        first
+    } else {
+        Unit::None
+    }
+}
+
+fn infer_expr(expr: &str) -> Unit {
+    let names = words(expr)
+        .into_iter()
+        .filter(|(_, word)| word.contains('_'));
+    for (offset, name) in names {
+        let name = name
+            .strip_suffix("_total")
+            .or_else(|| name.strip_suffix("_bucket"))
+            .unwrap_or(name);
+        if name.ends_with("_bytes") && per_second(expr, offset) {
+            return Unit::Binbps;
+        }
+        if name.ends_with("_bytes") {
+            return Unit::Bytes;
+        }
+        if name.ends_with("_seconds") {
+            return Unit::Seconds;
+        }
+        if name.ends_with("_ratio") {
+            return Unit::Percentunit;
+        }
+        if name.ends_with("_percent") {
+            return Unit::Percent;
+        }
+    }
+    Unit::None
+}
+
+/// Metric names, function names and other words of `expr`, with their byte offsets.
+fn words(expr: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in expr.char_indices() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+            start.get_or_insert(i);
+        } else if let Some(start) = start.take() {
+            words.push((start, &expr[start..i]));
+        }
+    }
+    if let Some(start) = start {
+        words.push((start, &expr[start..]));
+    }
+    words
+}
+
+/// Whether the selector at `offset` is the argument of `rate()` or `irate()`, which
+/// turn counters into per-second values.
+fn per_second(expr: &str, offset: usize) -> bool {
+    let mut depth = 0;
+    for (i, c) in expr[..offset].char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth > 0 => depth -= 1,
+            '(' => {
+                let before = expr[..i].trim_end();
+                return matches!(words(before).last(),
+                    Some(&(start, function)) if start + function.len() == before.len()
+                        && matches!(function, "rate" | "irate"));
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Divides by `base` until the value fits, then appends the matching prefix.
+fn scaled(value: f64, base: f64, prefixes: &[&str], suffix: &str) -> String {
+    let mut scaled = value;
+    let mut prefix = 0;
+    while reaches(scaled, base) && prefix + 1 < prefixes.len() {
+        scaled /= base;
+        prefix += 1;
+    }
+    let separator = if suffix.is_empty() { "" } else { " " };
+    format!("{:.2}{}{}{}", scaled, separator, prefixes[prefix], suffix)
+}
+
+/// Shows the duration in the largest unit it makes at least one of.
+fn duration(seconds: f64) -> String {
+    if seconds == 0.0 {
+        return "0 s".to_string();
+    }
+    let (size, suffix) = DURATION_UNITS
+        .iter()
+        .rev()
+        .find(|&&(size, _)| reaches(seconds / size, 1.0))
+        .unwrap_or(&DURATION_UNITS[0]);
+    format!("{:.2} {}", seconds / size, suffix)
+}
+
+/// Whether `value` shows as at least `limit` with two decimals, so that 999.999
+/// moves on to the next unit rather than showing as 1000.00.
+fn reaches(value: f64, limit: f64) -> bool {
+    (value.abs() * 100.0).round() >= limit * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_plain() {
+        assert_eq!(Unit::None.format(1.0 / 3.0), "0.33");
+        assert_eq!(Unit::None.format(f64::NAN), "NaN");
+        assert_eq!(Unit::None.format(f64::INFINITY), "inf");
+        assert_eq!(Unit::Percent.format(12.345), "12.35%");
+        assert_eq!(Unit::Percentunit.format(0.5), "50.00%");
+    }
+
+    #[test]
+    fn format_prefixes() {
+        assert_eq!(Unit::Short.format(999.0), "999.00");
+        assert_eq!(Unit::Short.format(1000.0), "1.00k");
+        assert_eq!(Unit::Short.format(-1500.0), "-1.50k");
+        assert_eq!(Unit::Bytes.format(1023.0), "1023.00 B");
+        assert_eq!(Unit::Bytes.format(1024.0), "1.00 KiB");
+        assert_eq!(Unit::Decbytes.format(1e9), "1.00 GB");
+        assert_eq!(Unit::Binbps.format(3.0 * 1024.0 * 1024.0), "3.00 MiB/s");
+        assert_eq!(Unit::Ops.format(2500.0), "2.50k ops/s");
+        // Past the largest prefix the number grows instead
+        assert_eq!(Unit::Short.format(2e21), "2000.00E");
+    }
+
+    #[test]
+    fn format_rounding_moves_to_next_prefix() {
+        assert_eq!(Unit::Short.format(999.996), "1.00k");
+        assert_eq!(Unit::Bytes.format(1023.999), "1.00 KiB");
+        assert_eq!(Unit::Seconds.format(59.999), "1.00 min");
+        assert_eq!(Unit::Seconds.format(0.999999), "1.00 s");
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(Unit::Seconds.format(0.0), "0 s");
+        assert_eq!(Unit::Seconds.format(5e-10), "0.50 ns");
+        assert_eq!(Unit::Seconds.format(0.0005), "500.00 µs");
+        assert_eq!(Unit::Seconds.format(-0.25), "-250.00 ms");
+        assert_eq!(Unit::Seconds.format(90.0), "1.50 min");
+        assert_eq!(Unit::Seconds.format(7200.0), "2.00 h");
+        assert_eq!(Unit::Seconds.format(3.0 * 86400.0), "3.00 d");
+        assert_eq!(Unit::Milliseconds.format(1500.0), "1.50 s");
+    }
+
+    #[test]
+    fn infer_expr_from_suffix() {
+        assert_eq!(infer_expr("node_memory_MemFree_bytes"), Unit::Bytes);
+        assert_eq!(
+            infer_expr("http_request_duration_seconds_bucket"),
+            Unit::Seconds
+        );
+        assert_eq!(infer_expr("cache_hit_ratio"), Unit::Percentunit);
+        assert_eq!(infer_expr("up"), Unit::None);
+    }
+
+    #[test]
+    fn infer_expr_rate_of_bytes() {
+        assert_eq!(infer_expr("rate(x_bytes_total[5m])"), Unit::Binbps);
+        assert_eq!(
+            infer_expr("sum by (job) (irate(x_bytes_total[1m]))"),
+            Unit::Binbps
+        );
+        assert_eq!(
+            infer_expr("rate (x_bytes_total{a=\"b\"}[5m])"),
+            Unit::Binbps
+        );
+        assert_eq!(infer_expr("increase(x_bytes_total[1h])"), Unit::Bytes);
+        assert_eq!(infer_expr("sum(x_bytes_total)"), Unit::Bytes);
+        assert_eq!(infer_expr("accelerate(x_bytes_total[5m])"), Unit::Bytes);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+
+use miette::{IntoDiagnostic, LabeledSpan, Result, WrapErr, miette};
+use prometheus_http_query::Client;
+use serde::Deserialize;
+
+use crate::{promql::Query, timerange::TimeRange};
+
+const LABEL_VALUES_HELP: &str = "use label_values(label) or label_values(selector, label)";
+
+/// A variable definition from the config file.
+///
+/// ```toml
+/// [variables.instance]
+/// query = "label_values(up{job='node'}, instance)"
+/// default = "web-1:9100"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariableConfig {
+    /// Where the values come from [default: label_values(<name>)]
+    pub query: Option<String>,
+    /// Initial value [default: the first value of the query]
+    pub default: Option<String>,
+}
+
+/// A template variable, referenced as `$name`, `${name}` or `[[name]]` in expressions
+/// and legends.
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub name: String,
+    pub source: LabelValues,
+    pub value: Option<String>,
+}
+
+/// A `label_values([selector,] label)` query listing the values of a variable.
+#[derive(Clone, Debug)]
+pub struct LabelValues {
+    selector: Option<String>,
+    label: String,
+}
+
+#[derive(Deserialize)]
+struct LabelValuesResponse {
+    data: Vec<String>,
+}
+
+impl LabelValues {
+    pub fn parse(query: &str) -> Result<Self> {
+        let invalid = |label: &str| {
+            miette!(
+                labels = vec![LabeledSpan::at(0..query.len(), label)],
+                help = LABEL_VALUES_HELP,
+                "invalid variable query `{}`",
+                query
+            )
+            .with_source_code(query.to_string())
+        };
+
+        let args = query
+            .trim()
+            .strip_prefix("label_values(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| invalid("not a label_values() query"))?;
+
+        // Selectors may contain commas themselves, but label names never do
+        let (selector, label) = match args.rsplit_once(',') {
+            Some((selector, label)) => (Some(selector.trim().to_string()), label.trim()),
+            None => (None, args.trim()),
+        };
+        if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid("not a label name"));
+        }
+
+        Ok(Self {
+            selector,
+            label: label.to_string(),
+        })
+    }
+
+    fn label(label: &str) -> Self {
+        Self {
+            selector: None,
+            label: label.to_string(),
+        }
+    }
+
+    /// Fetches the values seen within `range` from `/api/v1/label/<label>/values`.
+    pub async fn fetch(&self, client: &Client, range: &TimeRange) -> Result<Vec<String>> {
+        let (start, end) = range.bounds();
+        let url = format!(
+            "{}/api/v1/label/{}/values",
+            client.base_url().as_str().trim_end_matches('/'),
+            self.label
+        );
+        let mut params = vec![("start", start.to_string()), ("end", end.to_string())];
+        if let Some(selector) = &self.selector {
+            params.push(("match[]", selector.clone()));
+        }
+
+        let response = client
+            .inner()
+            .get(url)
+            .query(&params)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to list values of label `{}`", self.label))?;
+        let body = response.bytes().await.into_diagnostic()?;
+        let values: LabelValuesResponse = serde_json::from_slice(&body)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("invalid response listing label `{}`", self.label))?;
+        Ok(values.data)
+    }
+}
+
+/// Collects the variables given with `--var` and the configured ones the queries use.
+/// Command line values win over configured defaults.
+pub fn from_args(
+    args: &[String],
+    config: &BTreeMap<String, VariableConfig>,
+    queries: &[Query],
+) -> Result<Vec<Variable>> {
+    let mut values = parse_args(args)?;
+    let used = |name: &str| {
+        queries.iter().any(|q| {
+            references(&q.expr, name) || q.legend.as_deref().is_some_and(|l| references(l, name))
+        })
+    };
+
+    let mut variables = Vec::new();
+    for (name, var) in config {
+        if !values.contains_key(name) && !used(name) {
+            continue;
+        }
+        let source = match &var.query {
+            Some(query) => LabelValues::parse(query)
+                .wrap_err_with(|| format!("in the definition of variable `{}`", name))?,
+            None => LabelValues::label(name),
+        };
+        variables.push(Variable {
+            name: name.clone(),
+            source,
+            value: values.remove(name).or_else(|| var.default.clone()),
+        });
+    }
+    variables.extend(values.into_iter().map(|(name, value)| Variable {
+        source: LabelValues::label(&name),
+        name,
+        value: Some(value),
+    }));
+    Ok(variables)
+}
+
+/// Parses `--var name=value` arguments.
+pub fn parse_args(args: &[String]) -> Result<BTreeMap<String, String>> {
+    args.iter()
+        .map(|arg| {
+            let (name, value) = arg.split_once('=').ok_or_else(|| {
+                miette!(help = "use --var name=value", "invalid variable `{}`", arg)
+            })?;
+            Ok((name.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Gives variables without a value the first one their query returns.
+pub async fn resolve(client: &Client, variables: &mut [Variable], range: &TimeRange) -> Result<()> {
+    for var in variables.iter_mut().filter(|v| v.value.is_none()) {
+        let values = var.source.fetch(client, range).await?;
+        let first = values.into_iter().next().ok_or_else(|| {
+            miette!(
+                help = format!("set a value with --var {}=<value>", var.name),
+                "no values found for variable `{}`",
+                var.name
+            )
+        })?;
+        var.value = Some(first);
+    }
+    Ok(())
+}
+
+/// Returns `queries` with the variables' values filled in.
+pub fn apply(queries: &[Query], variables: &[Variable]) -> Vec<Query> {
+    let values: BTreeMap<String, String> = variables
+        .iter()
+        .filter_map(|v| Some((v.name.clone(), v.value.clone()?)))
+        .collect();
+    queries
+        .iter()
+        .map(|q| Query {
+            expr: substitute(&q.expr, &values),
+            legend: q.legend.as_ref().map(|l| substitute(l, &values)),
+        })
+        .collect()
+}
+
+fn references(text: &str, name: &str) -> bool {
+    let probe = BTreeMap::from([(name.to_string(), String::new())]);
+    substitute(text, &probe) != text
+}
+
+/// Names of the variables `text` references, in order, Grafana's `$__` globals
+/// included. Regex group references like `$1` are not variables.
+pub fn names(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find(['$', '[']) {
+        rest = &rest[pos..];
+        let (name, len) = reference(rest);
+        if len > 0 && name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            names.push(name);
+            rest = &rest[len..];
+        } else {
+            rest = &rest[1..];
+        }
+    }
+    names
+}
+
+/// The name of the variable referenced at the start of `rest` and the length of
+/// the reference, which is 0 when there is none.
+fn reference(rest: &str) -> (&str, usize) {
+    if let Some(inner) = rest.strip_prefix("${") {
+        match inner.find('}') {
+            Some(end) => {
+                let name = inner[..end].split(':').next().unwrap_or_default();
+                (name, end + 3)
+            }
+            None => ("", 0),
+        }
+    } else if let Some(inner) = rest.strip_prefix("[[") {
+        match inner.find("]]") {
+            Some(end) => (&inner[..end], end + 4),
+            None => ("", 0),
+        }
+    } else if let Some(inner) = rest.strip_prefix('$') {
+        let end = inner
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(inner.len());
+        (&inner[..end], end + 1)
+    } else {
+        ("", 0)
+    }
+}
+
+/// Replaces `$var`, `${var}`, `${var:format}` and `[[var]]` with the variable's
+/// value. Unknown names, including Grafana's `$__` globals, are left alone.
+pub fn substitute(text: &str, values: &BTreeMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find(['$', '[']) {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let (name, len) = reference(rest);
+        match values.get(name) {
+            Some(value) if len > 0 => {
+                result.push_str(value);
+                rest = &rest[len..];
+            }
+            _ => {
+                result.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("job".to_string(), "api".to_string()),
+            ("instance".to_string(), "web-1".to_string()),
+        ])
+    }
+
+    #[test]
+    fn substitute_syntaxes() {
+        assert_eq!(
+            substitute("up{job=\"$job\",instance=\"${instance}\"}", &values()),
+            "up{job=\"api\",instance=\"web-1\"}"
+        );
+        assert_eq!(substitute("${job:regex} [[job]]", &values()), "api api");
+    }
+
+    #[test]
+    fn substitute_leaves_unknown_names() {
+        let text = "rate(x{a=\"$jobs\"}[$__rate_interval]) $ [[other]] ${unclosed";
+        assert_eq!(substitute(text, &values()), text);
+        // Names run to the first character that cannot be in one
+        assert_eq!(substitute("$job_name $job.", &values()), "$job_name api.");
+    }
+
+    #[test]
+    fn names_in_order() {
+        assert_eq!(
+            names("rate(x{a=\"$a\",b=~\"${b:regex}\"}[$__interval]) [[c]]"),
+            vec!["a", "b", "__interval", "c"]
+        );
+        assert!(names("label_replace(up, \"x\", \"$1\", \"job\", \"(.*)$\")").is_empty());
+    }
+
+    #[test]
+    fn label_values_queries() {
+        let query = LabelValues::parse("label_values(up{job=~\"a,b\"}, instance)").unwrap();
+        assert_eq!(query.selector.as_deref(), Some("up{job=~\"a,b\"}"));
+        assert_eq!(query.label, "instance");
+        assert!(
+            LabelValues::parse("label_values(job)")
+                .unwrap()
+                .selector
+                .is_none()
+        );
+        for query in ["label_values()", "values(job)", "label_values(up, a-b)"] {
+            assert!(LabelValues::parse(query).is_err(), "{query}");
+        }
+    }
+
+    #[test]
+    fn parse_args_splits_on_first_equals() {
+        let args = parse_args(&["a=b=c".to_string()]).unwrap();
+        assert_eq!(args["a"], "b=c");
+        assert!(parse_args(&["a".to_string()]).is_err());
+    }
+}